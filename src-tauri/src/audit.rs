@@ -0,0 +1,385 @@
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+use crate::client::Api;
+use crate::models::{AuditAction, AuditEntry, FieldChange};
+use crate::{err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppAuditEntry {
+    id: i32,
+    user_name: String,
+    created: String,
+    action: AuditAction,
+    entity: String,
+    entity_id: i32,
+    changes: Vec<FieldChange>,
+}
+
+/// Narrows down the audit log. Every filter that's set has to match, and `text` is looked
+/// for in the values before and after a change.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct AuditFilter {
+    #[serde(default)]
+    user_name: Option<String>,
+    #[serde(default)]
+    entity: Option<String>,
+    #[serde(default)]
+    entity_id: Option<i32>,
+    #[serde(default)]
+    action: Option<AuditAction>,
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    to: Option<String>,
+    #[serde(default)]
+    field: Option<String>,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Whether the chain of hashes is whole. `broken_at` is the first entry that was changed,
+/// or that follows one that was removed.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AuditCheck {
+    entries: usize,
+    broken_at: Option<i32>,
+}
+
+impl AppAuditEntry {
+    fn from_entry(entry: AuditEntry) -> Self {
+        AppAuditEntry {
+            id: entry.id,
+            user_name: entry.user_name,
+            created: entry.created.format("%m/%d/%Y %H:%M").to_string(),
+            action: entry.action,
+            entity: entry.entity,
+            entity_id: entry.entity_id,
+            changes: entry.changes,
+        }
+    }
+}
+
+fn fields<T: Serialize>(record: Option<&T>) -> Result<Map<String, Value>, Error> {
+    match record.map(serde_json::to_value).transpose()? {
+        Some(Value::Object(fields)) => Ok(fields),
+        Some(_) => Err(anyhow!("Only records with fields can be audited")),
+        None => Ok(Map::new()),
+    }
+}
+
+/// The fields that differ between two versions of a record.
+pub fn diff<T: Serialize>(
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<Vec<FieldChange>, Error> {
+    let before = fields(before)?;
+    let after = fields(after)?;
+    Ok(before
+        .keys()
+        .chain(after.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            before: before.get(field).cloned(),
+            after: after.get(field).cloned(),
+        })
+        .collect())
+}
+
+fn entry_hash(entry: &AuditEntry) -> Result<String, Error> {
+    let content = serde_json::to_string(&(
+        &entry.previous_hash,
+        &entry.user_name,
+        entry.created,
+        entry.action,
+        &entry.entity,
+        entry.entity_id,
+        &entry.changes,
+    ))?;
+    Ok(format!("{:x}", Sha256::digest(content.as_bytes())))
+}
+
+/// The first of a run of entries, in the order they were written, whose hash doesn't follow
+/// from the ones before it.
+fn broken_at(entries: &[AuditEntry]) -> Result<Option<i32>, Error> {
+    let mut previous_hash = "";
+    for entry in entries {
+        if entry.previous_hash != previous_hash || entry_hash(entry)? != entry.hash {
+            return Ok(Some(entry.id));
+        }
+        previous_hash = &entry.hash;
+    }
+    Ok(None)
+}
+
+async fn record<T: Serialize>(
+    api: &Api,
+    action: AuditAction,
+    entity: &str,
+    entity_id: i32,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), Error> {
+    let changes = diff(before, after)?;
+    if action == AuditAction::Update && changes.is_empty() {
+        return Ok(());
+    }
+    let previous_hash = api
+        .last_audit_entry()
+        .await?
+        .map(|entry| entry.hash)
+        .unwrap_or_default();
+    let mut entry = AuditEntry {
+        id: 0,
+        user_name: api.user_name().to_string(),
+        // Whole seconds, so the time hashes the same after the backend has stored it.
+        created: Utc::now().naive_utc().with_nanosecond(0).unwrap(),
+        action,
+        entity: entity.to_string(),
+        entity_id,
+        changes,
+        previous_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry_hash(&entry)?;
+    api.new_audit_entry(&entry).await?;
+    Ok(())
+}
+
+pub async fn created<T: Serialize>(
+    api: &Api,
+    entity: &str,
+    entity_id: i32,
+    after: &T,
+) -> Result<(), Error> {
+    record(
+        api,
+        AuditAction::Create,
+        entity,
+        entity_id,
+        None,
+        Some(after),
+    )
+    .await
+}
+
+/// Records the fields a save changed. Saves that didn't change anything aren't recorded.
+pub async fn updated<T: Serialize>(
+    api: &Api,
+    entity: &str,
+    entity_id: i32,
+    before: &T,
+    after: &T,
+) -> Result<(), Error> {
+    record(
+        api,
+        AuditAction::Update,
+        entity,
+        entity_id,
+        Some(before),
+        Some(after),
+    )
+    .await
+}
+
+pub async fn deleted<T: Serialize>(
+    api: &Api,
+    entity: &str,
+    entity_id: i32,
+    before: &T,
+) -> Result<(), Error> {
+    record(
+        api,
+        AuditAction::Delete,
+        entity,
+        entity_id,
+        Some(before),
+        None,
+    )
+    .await
+}
+
+fn parse_day(day: &Option<String>) -> Result<Option<NaiveDate>, Error> {
+    day.as_ref()
+        .map(|day| NaiveDate::parse_from_str(day, "%m/%d/%Y"))
+        .transpose()
+        .context("Can't convert string to date")
+}
+
+fn contains_text(value: &Option<Value>, text: &str) -> bool {
+    match value {
+        Some(Value::String(value)) => value.to_lowercase().contains(text),
+        Some(value) => value.to_string().to_lowercase().contains(text),
+        None => false,
+    }
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry, from: Option<NaiveDate>, to: Option<NaiveDate>) -> bool {
+        let day = entry.created.date();
+        let text = self.text.as_ref().map(|text| text.trim().to_lowercase());
+        self.user_name.as_ref().map_or(true, |user_name| {
+            user_name.eq_ignore_ascii_case(&entry.user_name)
+        }) && self
+            .entity
+            .as_ref()
+            .map_or(true, |entity| entity.eq_ignore_ascii_case(&entry.entity))
+            && self.entity_id.map_or(true, |id| id == entry.entity_id)
+            && self.action.map_or(true, |action| action == entry.action)
+            && from.map_or(true, |from| day >= from)
+            && to.map_or(true, |to| day <= to)
+            && self.field.as_ref().map_or(true, |field| {
+                entry
+                    .changes
+                    .iter()
+                    .any(|change| change.field.eq_ignore_ascii_case(field))
+            })
+            && text.map_or(true, |text| {
+                entry.changes.iter().any(|change| {
+                    contains_text(&change.before, &text) || contains_text(&change.after, &text)
+                })
+            })
+    }
+}
+
+/// Audit entries that match a filter, newest first.
+#[tauri::command]
+pub async fn audit_log(
+    state: tauri::State<'_, AppState>,
+    filter: Option<AuditFilter>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AppAuditEntry>, String> {
+    let filter = filter.unwrap_or_default();
+    let from = parse_day(&filter.from).map_err(err_to_string)?;
+    let to = parse_day(&filter.to).map_err(err_to_string)?;
+    let mut entries = state
+        .0
+        .lock()
+        .await
+        .all_audit_entries()
+        .await
+        .map_err(err_to_string)?;
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.id));
+    Ok(entries
+        .into_iter()
+        .filter(|entry| filter.matches(entry, from, to))
+        .skip(offset as usize)
+        .take(limit as usize)
+        .map(AppAuditEntry::from_entry)
+        .collect())
+}
+
+/// Walks the audit log from the first entry and checks every hash and link.
+#[tauri::command]
+pub async fn verify_audit_log(state: tauri::State<'_, AppState>) -> Result<AuditCheck, String> {
+    let mut entries = state
+        .0
+        .lock()
+        .await
+        .all_audit_entries()
+        .await
+        .map_err(err_to_string)?;
+    entries.sort_by_key(|entry| entry.id);
+    Ok(AuditCheck {
+        entries: entries.len(),
+        broken_at: broken_at(&entries).map_err(err_to_string)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(id: i32, previous_hash: &str) -> AuditEntry {
+        let mut entry = AuditEntry {
+            id,
+            user_name: String::from("clerk"),
+            created: NaiveDate::from_ymd_opt(2024, 3, 1)
+                .unwrap()
+                .and_hms_opt(9, 30, 0)
+                .unwrap(),
+            action: AuditAction::Update,
+            entity: String::from("product"),
+            entity_id: 7,
+            changes: vec![FieldChange {
+                field: String::from("name"),
+                before: Some(json!("Soap")),
+                after: Some(json!("Hand soap")),
+            }],
+            previous_hash: previous_hash.to_string(),
+            hash: String::new(),
+        };
+        entry.hash = entry_hash(&entry).unwrap();
+        entry
+    }
+
+    fn chain(length: i32) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> = Vec::new();
+        for id in 1..=length {
+            let previous_hash = entries.last().map_or("", |entry| &entry.hash).to_string();
+            entries.push(entry(id, &previous_hash));
+        }
+        entries
+    }
+
+    #[test]
+    fn diff_lists_only_changed_fields() {
+        let before = json!({ "name": "Soap", "upc": "123", "amount": 4 });
+        let after = json!({ "name": "Hand soap", "upc": "123", "buy_level": 2 });
+        let changes = diff(Some(&before), Some(&after)).unwrap();
+        let fields = changes
+            .iter()
+            .map(|change| change.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, ["amount", "buy_level", "name"]);
+        assert_eq!(changes[0].after, None);
+        assert_eq!(changes[1].before, None);
+        assert_eq!(changes[2].after, Some(json!("Hand soap")));
+    }
+
+    #[test]
+    fn diff_of_a_created_record_lists_every_field() {
+        let after = json!({ "name": "Soap", "upc": "123" });
+        let changes = diff(None, Some(&after)).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|change| change.before.is_none()));
+        assert!(diff(Some(&json!(1)), None::<&Value>).is_err());
+    }
+
+    #[test]
+    fn hash_depends_on_the_content_but_not_the_id() {
+        let first = entry(1, "");
+        assert_eq!(entry_hash(&first).unwrap(), first.hash);
+        assert_eq!(entry(2, "").hash, first.hash);
+        assert_ne!(entry(1, "abc").hash, first.hash);
+        let mut edited = first.clone();
+        edited.changes[0].after = Some(json!("Dish soap"));
+        assert_ne!(entry_hash(&edited).unwrap(), first.hash);
+    }
+
+    #[test]
+    fn an_untouched_chain_verifies() {
+        assert_eq!(broken_at(&chain(4)).unwrap(), None);
+        assert_eq!(broken_at(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn an_edited_or_removed_entry_breaks_the_chain() {
+        let mut edited = chain(4);
+        edited[1].user_name = String::from("manager");
+        assert_eq!(broken_at(&edited).unwrap(), Some(2));
+
+        let mut removed = chain(4);
+        removed.remove(2);
+        assert_eq!(broken_at(&removed).unwrap(), Some(4));
+    }
+}
@@ -0,0 +1,471 @@
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::client::Api;
+use crate::models::{
+    ArchivedEntity, ArchivedRecord, AuditEntry, Brand, Category, CountScope, CountSession,
+    Location, LocationStock, Lot, PendingOrder, PosImport, PriceChange, Product, ReceivedOrder,
+    Sale, Serial, StockMovement, Supplier, SupplierItem, Transfer, VendorReturn,
+};
+use crate::{err_to_string, AppState};
+
+/// Bumped whenever the layout of an archive changes, so an old build doesn't misread a
+/// newer backup.
+const BACKUP_VERSION: u32 = 2;
+
+/// One list in an archive, with a checksum of its contents so a damaged or edited backup
+/// is caught before anything is restored.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    entity: String,
+    count: usize,
+    sha256: String,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Manifest {
+    version: u32,
+    created: NaiveDateTime,
+    source: String,
+    user_name: String,
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Archive {
+    manifest: Manifest,
+    data: BTreeMap<String, Value>,
+}
+
+fn checksum(value: &Value) -> Result<String, Error> {
+    Ok(format!(
+        "{:x}",
+        Sha256::digest(serde_json::to_string(value)?.as_bytes())
+    ))
+}
+
+impl Archive {
+    fn add<T: Serialize>(&mut self, entity: &str, items: &[T]) -> Result<(), Error> {
+        let value = serde_json::to_value(items)?;
+        self.manifest.entries.push(ManifestEntry {
+            entity: entity.to_string(),
+            count: items.len(),
+            sha256: checksum(&value)?,
+        });
+        self.data.insert(entity.to_string(), value);
+        Ok(())
+    }
+
+    /// Checks the version and every checksum, before any of the archive is used.
+    fn verify(&self) -> Result<(), Error> {
+        if self.manifest.version > BACKUP_VERSION {
+            return Err(anyhow!(
+                "Backup is version {}, this version of the app reads up to {}",
+                self.manifest.version,
+                BACKUP_VERSION
+            ));
+        }
+        for entry in &self.manifest.entries {
+            let value = self
+                .data
+                .get(&entry.entity)
+                .ok_or_else(|| anyhow!("Backup is missing its {}", entry.entity))?;
+            if checksum(value)? != entry.sha256 {
+                return Err(anyhow!(
+                    "Backup's {} don't match its checksum",
+                    entry.entity
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn get<T: DeserializeOwned>(&self, entity: &str) -> Result<Vec<T>, Error> {
+        match self.data.get(entity) {
+            Some(value) => serde_json::from_value(value.clone())
+                .with_context(|| format!("Can't read the {} in the backup", entity)),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// New ids of restored records, by the ids they had in the backup.
+struct Ids {
+    entity: &'static str,
+    ids: HashMap<i32, i32>,
+}
+
+impl Ids {
+    fn new(entity: &'static str) -> Self {
+        Ids {
+            entity,
+            ids: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, old: i32, new: i32) {
+        self.ids.insert(old, new);
+    }
+
+    fn get(&self, old: i32) -> Result<i32, Error> {
+        self.ids.get(&old).copied().ok_or_else(|| {
+            anyhow!(
+                "Backup refers to {} #{}, which isn't in it",
+                self.entity,
+                old
+            )
+        })
+    }
+
+    fn optional(&self, old: Option<i32>) -> Result<Option<i32>, Error> {
+        old.map(|old| self.get(old)).transpose()
+    }
+}
+
+/// The ids of the brands, categories or suppliers that list a product.
+fn listed_under(lists: &[(i32, &Vec<Option<i32>>)], product_id: i32) -> Vec<i32> {
+    lists
+        .iter()
+        .filter(|(_, products)| products.contains(&Some(product_id)))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+async fn backup_archive(api: &Api) -> Result<Archive, Error> {
+    let mut archive = Archive {
+        manifest: Manifest {
+            version: BACKUP_VERSION,
+            created: Utc::now().naive_utc(),
+            source: api.base_url().to_string(),
+            user_name: api.user_name().to_string(),
+            entries: Vec::new(),
+        },
+        data: BTreeMap::new(),
+    };
+    let products = api.all_products().await?;
+    let suppliers = api.all_suppliers().await?;
+    let locations = api.get_locations().await?;
+    let mut location_stock = Vec::new();
+    for location in &locations {
+        location_stock.extend(api.get_location_stock(location.id).await?);
+    }
+    let mut supplier_items = Vec::new();
+    for supplier in &suppliers {
+        supplier_items.extend(api.get_supplier_items(supplier.id).await?);
+    }
+    let mut price_changes = Vec::new();
+    let mut stock_movements = Vec::new();
+    let mut serials = Vec::new();
+    for product in &products {
+        price_changes.extend(api.get_price_history(product.id).await?);
+        stock_movements.extend(api.get_stock_movements(product.id).await?);
+        serials.extend(api.get_product_serials(product.id).await?);
+    }
+
+    archive.add("brands", &api.all_brands().await?)?;
+    archive.add("categories", &api.all_categories().await?)?;
+    archive.add("suppliers", &suppliers)?;
+    archive.add("locations", &locations)?;
+    archive.add("products", &products)?;
+    archive.add("location_stock", &location_stock)?;
+    archive.add("supplier_items", &supplier_items)?;
+    archive.add("price_changes", &price_changes)?;
+    archive.add("pending_orders", &api.all_pending_orders().await?)?;
+    archive.add("received_orders", &api.all_received_orders().await?)?;
+    archive.add("stock_movements", &stock_movements)?;
+    archive.add("lots", &api.all_lots().await?)?;
+    archive.add("serials", &serials)?;
+    archive.add("transfers", &api.all_transfers().await?)?;
+    archive.add("count_sessions", &api.all_count_sessions().await?)?;
+    archive.add("sales", &api.all_sales().await?)?;
+    archive.add("vendor_returns", &api.all_vendor_returns().await?)?;
+    archive.add("archived_records", &api.get_archived_records().await?)?;
+    archive.add("pos_imports", &api.all_pos_imports().await?)?;
+    archive.add("audit_entries", &api.all_audit_entries().await?)?;
+    Ok(archive)
+}
+
+async fn is_empty(api: &Api) -> Result<bool, Error> {
+    Ok(api.product_names().await?.is_empty()
+        && api.brand_names().await?.is_empty()
+        && api.category_names().await?.is_empty()
+        && api.supplier_names().await?.is_empty()
+        && api.get_locations().await?.is_empty()
+        && api.last_audit_entry().await?.is_none())
+}
+
+async fn restore_archive(api: &Api, archive: &Archive) -> Result<(), Error> {
+    let brands: Vec<Brand> = archive.get("brands")?;
+    let categories: Vec<Category> = archive.get("categories")?;
+    let suppliers: Vec<Supplier> = archive.get("suppliers")?;
+    let mut locations: Vec<Location> = archive.get("locations")?;
+    let mut products: Vec<Product> = archive.get("products")?;
+
+    let mut brand_ids = Ids::new("brand");
+    for brand in &brands {
+        brand_ids.insert(brand.id, api.new_brand(&brand.name).await?);
+    }
+    let mut category_ids = Ids::new("category");
+    for category in &categories {
+        category_ids.insert(category.id, api.new_category(&category.name).await?);
+    }
+    let mut supplier_ids = Ids::new("supplier");
+    for supplier in &suppliers {
+        let id = api
+            .new_supplier(
+                &supplier.name,
+                supplier.phone_number.as_deref().unwrap_or_default(),
+                supplier.email.as_deref().unwrap_or_default(),
+            )
+            .await?;
+        supplier_ids.insert(supplier.id, id);
+    }
+    // Parents have to exist before the locations inside them.
+    let mut location_ids = Ids::new("location");
+    while !locations.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = locations.into_iter().partition(|location| {
+            location
+                .parent_id
+                .map_or(true, |parent| location_ids.ids.contains_key(&parent))
+        });
+        if ready.is_empty() {
+            return Err(anyhow!(
+                "Backup's locations are inside each other in a loop"
+            ));
+        }
+        for location in ready {
+            let parent_id = location_ids.optional(location.parent_id)?;
+            let id = api
+                .new_location(&location.name, location.kind, parent_id)
+                .await?;
+            location_ids.insert(location.id, id);
+        }
+        locations = waiting;
+    }
+
+    let brand_lists = brands
+        .iter()
+        .map(|brand| (brand.id, &brand.products))
+        .collect::<Vec<_>>();
+    let category_lists = categories
+        .iter()
+        .map(|category| (category.id, &category.products))
+        .collect::<Vec<_>>();
+    let supplier_lists = suppliers
+        .iter()
+        .map(|supplier| (supplier.id, &supplier.products))
+        .collect::<Vec<_>>();
+    let mut product_ids = Ids::new("product");
+    for product in &products {
+        let categories = listed_under(&category_lists, product.id)
+            .into_iter()
+            .map(|id| category_ids.get(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let suppliers = listed_under(&supplier_lists, product.id)
+            .into_iter()
+            .map(|id| supplier_ids.get(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let brand = brand_ids.optional(listed_under(&brand_lists, product.id).first().copied())?;
+        let id = api
+            .new_product(
+                &product.upc,
+                &product.name,
+                &product.description,
+                product.measure_by_weight,
+                product.cost_price_per_unit.clone(),
+                product.selling_price_per_unit.clone(),
+                product.buy_level.clone().unwrap_or_default(),
+                categories,
+                suppliers,
+                brand,
+            )
+            .await?;
+        product_ids.insert(product.id, id);
+    }
+
+    for mut item in archive.get::<SupplierItem>("supplier_items")? {
+        item.supplier_id = supplier_ids.get(item.supplier_id)?;
+        item.product_id = product_ids.get(item.product_id)?;
+        api.new_supplier_item(&item).await?;
+    }
+    for mut change in archive.get::<PriceChange>("price_changes")? {
+        change.product_id = product_ids.get(change.product_id)?;
+        api.new_price_change(&change).await?;
+    }
+    let mut pending_order_ids = Ids::new("pending order");
+    for order in archive.get::<PendingOrder>("pending_orders")? {
+        let id = api
+            .new_pending_order(order.amount.clone(), product_ids.get(order.product_id)?)
+            .await?;
+        api.update_pending_order(&PendingOrder {
+            id,
+            product_id: product_ids.get(order.product_id)?,
+            supplier_id: supplier_ids.optional(order.supplier_id)?,
+            ..order.clone()
+        })
+        .await?;
+        pending_order_ids.insert(order.id, id);
+    }
+    // Received orders can only be made by receiving a pending order.
+    let mut received_order_ids = Ids::new("received order");
+    for order in archive.get::<ReceivedOrder>("received_orders")? {
+        let product_id = product_ids.get(order.product_id)?;
+        let pending = api
+            .new_pending_order(order.gross_amount.clone(), product_id)
+            .await?;
+        let id = api
+            .mark_as_received(
+                pending,
+                order.received.unwrap_or_else(|| Utc::now().naive_utc()),
+                &order.actually_received,
+                &order.damaged,
+            )
+            .await?;
+        api.update_received_order(&ReceivedOrder {
+            id,
+            product_id,
+            supplier_id: supplier_ids.optional(order.supplier_id)?,
+            ..order.clone()
+        })
+        .await?;
+        received_order_ids.insert(order.id, id);
+    }
+    for mut movement in archive.get::<StockMovement>("stock_movements")? {
+        movement.product_id = product_ids.get(movement.product_id)?;
+        movement.location_id = location_ids.optional(movement.location_id)?;
+        api.new_stock_movement(&movement).await?;
+    }
+    for mut lot in archive.get::<Lot>("lots")? {
+        lot.product_id = product_ids.get(lot.product_id)?;
+        lot.received_order_id = received_order_ids.optional(lot.received_order_id)?;
+        api.new_lot(&lot).await?;
+    }
+    for mut serial in archive.get::<Serial>("serials")? {
+        serial.product_id = product_ids.get(serial.product_id)?;
+        serial.received_order_id = received_order_ids.optional(serial.received_order_id)?;
+        serial.supplier_id = supplier_ids.optional(serial.supplier_id)?;
+        api.new_serial(&serial).await?;
+    }
+    for mut transfer in archive.get::<Transfer>("transfers")? {
+        transfer.from_location_id = location_ids.get(transfer.from_location_id)?;
+        transfer.to_location_id = location_ids.get(transfer.to_location_id)?;
+        for line in &mut transfer.lines {
+            line.product_id = product_ids.get(line.product_id)?;
+        }
+        api.new_transfer(&transfer).await?;
+    }
+    for mut session in archive.get::<CountSession>("count_sessions")? {
+        session.scope = match session.scope {
+            CountScope::Category(id) => CountScope::Category(category_ids.get(id)?),
+            CountScope::Brand(id) => CountScope::Brand(brand_ids.get(id)?),
+            scope => scope,
+        };
+        for line in &mut session.lines {
+            line.product_id = product_ids.get(line.product_id)?;
+        }
+        api.new_count_session(&session).await?;
+    }
+    for mut sale in archive.get::<Sale>("sales")? {
+        sale.product_id = product_ids.get(sale.product_id)?;
+        sale.location_id = location_ids.optional(sale.location_id)?;
+        api.new_sale(&sale).await?;
+    }
+    for mut vendor_return in archive.get::<VendorReturn>("vendor_returns")? {
+        vendor_return.supplier_id = supplier_ids.get(vendor_return.supplier_id)?;
+        vendor_return.location_id = location_ids.optional(vendor_return.location_id)?;
+        for line in &mut vendor_return.lines {
+            line.product_id = product_ids.get(line.product_id)?;
+            line.received_order_id = received_order_ids.optional(line.received_order_id)?;
+        }
+        api.new_vendor_return(&vendor_return).await?;
+    }
+
+    // Stock levels and the links between products go last, so receiving the order history
+    // above doesn't add to them.
+    for product in &mut products {
+        product.id = product_ids.get(product.id)?;
+        product.parent_id = product_ids.optional(product.parent_id)?;
+        for component in &mut product.components {
+            component.product_id = product_ids.get(component.product_id)?;
+        }
+        api.update_product(product).await?;
+    }
+    for mut stock in archive.get::<LocationStock>("location_stock")? {
+        stock.location_id = location_ids.get(stock.location_id)?;
+        stock.product_id = product_ids.get(stock.product_id)?;
+        api.update_location_stock(&stock).await?;
+    }
+    for mut record in archive.get::<ArchivedRecord>("archived_records")? {
+        record.entity_id = match record.entity {
+            ArchivedEntity::Product => product_ids.get(record.entity_id)?,
+            ArchivedEntity::Brand => brand_ids.get(record.entity_id)?,
+            ArchivedEntity::Category => category_ids.get(record.entity_id)?,
+            ArchivedEntity::Supplier => supplier_ids.get(record.entity_id)?,
+            ArchivedEntity::PendingOrder => pending_order_ids.get(record.entity_id)?,
+            ArchivedEntity::ReceivedOrder => received_order_ids.get(record.entity_id)?,
+        };
+        api.new_archived_record(&record).await?;
+    }
+    // Imports name their lines by receipt or file, so they're restored unchanged and the
+    // same exports aren't posted again.
+    for pos_import in archive.get::<PosImport>("pos_imports")? {
+        api.new_pos_import(&pos_import).await?;
+    }
+    // The audit log is copied as it is, in the order it was written, so its chain of hashes
+    // still checks out. Its entries keep the ids records had in the backed up backend.
+    let mut audit_entries = archive.get::<AuditEntry>("audit_entries")?;
+    audit_entries.sort_by_key(|entry| entry.id);
+    for entry in &audit_entries {
+        api.new_audit_entry(entry).await?;
+    }
+    Ok(())
+}
+
+/// Writes everything in the backend to one JSON archive and returns its manifest.
+#[tauri::command]
+pub async fn backup(state: tauri::State<'_, AppState>, path: String) -> Result<Manifest, String> {
+    let archive = backup_archive(&*state.0.lock().await)
+        .await
+        .map_err(err_to_string)?;
+    let json = serde_json::to_string_pretty(&archive)
+        .context("Failed to serialize backup")
+        .map_err(err_to_string)?;
+    std::fs::write(&path, json)
+        .with_context(|| format!("Can't write {}", path))
+        .map_err(err_to_string)?;
+    Ok(archive.manifest)
+}
+
+/// Replays a backup into the backend that's logged in, which has to be empty. Everything
+/// gets a new id there, and the links between records are carried over to the new ids.
+/// Log in with a base URL to restore into a local server. A restore that fails partway
+/// leaves what it got through in the backend, which then has to be wiped by hand before
+/// the backup can be restored again.
+#[tauri::command]
+pub async fn restore(state: tauri::State<'_, AppState>, path: String) -> Result<Manifest, String> {
+    let json = std::fs::read_to_string(&path)
+        .with_context(|| format!("Can't read {}", path))
+        .map_err(err_to_string)?;
+    let archive: Archive = serde_json::from_str(&json)
+        .context("File isn't a backup")
+        .map_err(err_to_string)?;
+    archive.verify().map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    if !is_empty(&api).await.map_err(err_to_string)? {
+        return Err(String::from(
+            "Backups can only be restored into an empty backend",
+        ));
+    }
+    restore_archive(&api, &archive).await.map_err(|err| {
+        format!(
+            "Restore stopped partway ({}), wipe the backend before restoring again",
+            err
+        )
+    })?;
+    Ok(archive.manifest)
+}
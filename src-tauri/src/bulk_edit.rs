@@ -0,0 +1,336 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::audit;
+use crate::client::Api;
+use crate::export::{self, ExportFilter};
+use crate::models::{ArchivedEntity, FieldChange, Product};
+use crate::pricing::{record_price_change, PriceField};
+use crate::quantity::Quantity;
+use crate::recycle_bin;
+use crate::relations::{ProductRelations, Relations};
+use crate::units;
+use crate::{err_to_string, AppState};
+
+/// Which products a bulk edit applies to: the ones picked by id, or the ones that pass the
+/// same filters as the product export.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductSelection {
+    Ids(Vec<i32>),
+    Filter(Vec<ExportFilter>),
+}
+
+/// One change made to every selected product. Prices are adjusted by a percentage or a fixed
+/// amount and rounded to whole cents.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkOperation {
+    SetBrand(Option<i32>),
+    AddCategory(i32),
+    RemoveCategory(i32),
+    AddSupplier(i32),
+    RemoveSupplier(i32),
+    SetBuyLevel(Option<Quantity>),
+    AdjustPricePercent { field: PriceField, percent: String },
+    AdjustPriceAmount { field: PriceField, amount: String },
+    SetMeasureByWeight(bool),
+}
+
+/// A product a bulk edit changes, and what it changes. `error` says why it couldn't be.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BulkUpdateRow {
+    product_id: i32,
+    name: String,
+    changes: Vec<FieldChange>,
+    error: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BulkUpdateReport {
+    selected: usize,
+    updated: usize,
+    unchanged: usize,
+    failed: usize,
+    rows: Vec<BulkUpdateRow>,
+}
+
+/// The parts of a product a bulk edit can change, for comparing before and after.
+#[derive(Serialize)]
+struct Edited<'a> {
+    #[serde(flatten)]
+    product: &'a Product,
+    #[serde(flatten)]
+    relations: &'a ProductRelations,
+}
+
+fn decimal(value: &str) -> Result<BigDecimal, Error> {
+    BigDecimal::from_str(value.trim()).with_context(|| format!("{} is not a number", value))
+}
+
+/// Checks every operation before any product is touched.
+fn check_operations(operations: &[BulkOperation], relations: &Relations) -> Result<(), Error> {
+    if operations.is_empty() {
+        return Err(anyhow!("Pick at least one change to make"));
+    }
+    let mut referenced = ProductRelations::default();
+    for operation in operations {
+        match operation {
+            BulkOperation::SetBrand(brand_id) => referenced.brand_id = *brand_id,
+            BulkOperation::AddCategory(id) | BulkOperation::RemoveCategory(id) => {
+                referenced.category_ids.push(*id)
+            }
+            BulkOperation::AddSupplier(id) | BulkOperation::RemoveSupplier(id) => {
+                referenced.supplier_ids.push(*id)
+            }
+            BulkOperation::AdjustPricePercent { percent: value, .. }
+            | BulkOperation::AdjustPriceAmount { amount: value, .. } => {
+                decimal(value)?;
+            }
+            BulkOperation::SetBuyLevel(_) | BulkOperation::SetMeasureByWeight(_) => {}
+        }
+    }
+    relations.check(&referenced)
+}
+
+fn adjust(
+    product: &mut Product,
+    field: PriceField,
+    change: impl Fn(&BigDecimal) -> BigDecimal,
+) -> Result<(), Error> {
+    if field != PriceField::Selling {
+        product.cost_price_per_unit = change(&product.cost_price_per_unit).round(2);
+    }
+    if field != PriceField::Cost {
+        product.selling_price_per_unit = change(&product.selling_price_per_unit).round(2);
+    }
+    if product.cost_price_per_unit < BigDecimal::zero()
+        || product.selling_price_per_unit < BigDecimal::zero()
+    {
+        return Err(anyhow!("Prices can't drop below zero"));
+    }
+    Ok(())
+}
+
+fn edit(
+    operations: &[BulkOperation],
+    product: &mut Product,
+    relations: &mut ProductRelations,
+) -> Result<(), Error> {
+    for operation in operations {
+        match operation {
+            BulkOperation::SetBrand(brand_id) => relations.brand_id = *brand_id,
+            BulkOperation::AddCategory(id) => {
+                if !relations.category_ids.contains(id) {
+                    relations.category_ids.push(*id);
+                }
+            }
+            BulkOperation::RemoveCategory(id) => relations.category_ids.retain(|other| other != id),
+            BulkOperation::AddSupplier(id) => {
+                if !relations.supplier_ids.contains(id) {
+                    relations.supplier_ids.push(*id);
+                }
+            }
+            BulkOperation::RemoveSupplier(id) => relations.supplier_ids.retain(|other| other != id),
+            BulkOperation::SetBuyLevel(buy_level) => product.buy_level = buy_level.clone(),
+            BulkOperation::AdjustPricePercent { field, percent } => {
+                let factor = BigDecimal::from(1) + decimal(percent)? / BigDecimal::from(100);
+                adjust(product, *field, |price| price * &factor)?;
+            }
+            BulkOperation::AdjustPriceAmount { field, amount } => {
+                let amount = decimal(amount)?;
+                adjust(product, *field, |price| price + &amount)?;
+            }
+            BulkOperation::SetMeasureByWeight(measure_by_weight) => {
+                product.measure_by_weight = *measure_by_weight
+            }
+        }
+    }
+    // The same checks as saving a product by hand.
+    units::check_units(product)
+}
+
+/// A selected product before and after the edit, with its links after, or why it can't be
+/// edited.
+struct Planned {
+    product_id: i32,
+    name: String,
+    edit: Result<(Product, Product, ProductRelations), Error>,
+    changes: Vec<FieldChange>,
+}
+
+impl Planned {
+    fn row(&self, error: Option<String>) -> BulkUpdateRow {
+        BulkUpdateRow {
+            product_id: self.product_id,
+            name: self.name.clone(),
+            changes: self.changes.clone(),
+            error,
+        }
+    }
+}
+
+async fn selected(api: &Api, selection: &ProductSelection) -> Result<Vec<i32>, Error> {
+    match selection {
+        ProductSelection::Ids(ids) => {
+            let archived = recycle_bin::archived(api).await?;
+            if let Some(id) = ids
+                .iter()
+                .find(|id| archived.contains(ArchivedEntity::Product, **id))
+            {
+                return Err(anyhow!("Product #{} is in the recycle bin", id));
+            }
+            Ok(ids.clone())
+        }
+        ProductSelection::Filter(filters) => export::product_ids(api, filters).await,
+    }
+}
+
+async fn plan(
+    api: &Api,
+    selection: &ProductSelection,
+    operations: &[BulkOperation],
+    relations: &Relations,
+) -> Result<Vec<Planned>, Error> {
+    check_operations(operations, relations)?;
+    let mut planned = Vec::new();
+    for id in selected(api, selection).await? {
+        let before = match api.get_product(id).await {
+            Ok(before) => before,
+            Err(err) => {
+                planned.push(Planned {
+                    product_id: id,
+                    name: String::new(),
+                    edit: Err(anyhow!("Can't load product #{}: {}", id, err)),
+                    changes: Vec::new(),
+                });
+                continue;
+            }
+        };
+        let before_relations = relations.of(id);
+        let mut product = before.clone();
+        let mut product_relations = before_relations.clone();
+        let after = edit(operations, &mut product, &mut product_relations);
+        let changes = match &after {
+            Ok(()) => audit::diff(
+                Some(&Edited {
+                    product: &before,
+                    relations: &before_relations,
+                }),
+                Some(&Edited {
+                    product: &product,
+                    relations: &product_relations,
+                }),
+            )?,
+            Err(_) => Vec::new(),
+        };
+        planned.push(Planned {
+            product_id: id,
+            name: before.name.clone(),
+            edit: after.map(|_| (before, product, product_relations)),
+            changes,
+        });
+    }
+    Ok(planned)
+}
+
+/// Saves one product and its links, putting the product back if the links can't be saved.
+async fn save(
+    api: &Api,
+    relations: &mut Relations,
+    before: &Product,
+    after: &Product,
+    after_relations: &ProductRelations,
+    reason: &str,
+) -> Result<(), Error> {
+    if after != before {
+        api.update_product(after).await?;
+    }
+    if let Err(err) = relations.save(api, after.id, after_relations).await {
+        if after != before {
+            api.update_product(before).await?;
+        }
+        return Err(err);
+    }
+    if after != before {
+        record_price_change(api, before, after, reason).await?;
+        audit::updated(api, "product", after.id, before, after).await?;
+    }
+    Ok(())
+}
+
+/// The products a bulk edit would change and how, without changing anything. Products it
+/// wouldn't change are left out.
+#[tauri::command]
+pub async fn preview_bulk_update_products(
+    state: tauri::State<'_, AppState>,
+    selection: ProductSelection,
+    operations: Vec<BulkOperation>,
+) -> Result<Vec<BulkUpdateRow>, String> {
+    let api = state.0.lock().await;
+    let relations = Relations::load(&api).await.map_err(err_to_string)?;
+    Ok(plan(&api, &selection, &operations, &relations)
+        .await
+        .map_err(err_to_string)?
+        .iter()
+        .filter(|planned| planned.edit.is_err() || !planned.changes.is_empty())
+        .map(|planned| planned.row(planned.edit.as_ref().err().map(|err| err.to_string())))
+        .collect())
+}
+
+/// Makes a bulk edit. A product that can't be changed doesn't stop the rest, and the report
+/// says which ones failed and why.
+#[tauri::command]
+pub async fn bulk_update_products(
+    state: tauri::State<'_, AppState>,
+    selection: ProductSelection,
+    operations: Vec<BulkOperation>,
+    reason: Option<String>,
+) -> Result<BulkUpdateReport, String> {
+    let api = state.0.lock().await;
+    let mut relations = Relations::load(&api).await.map_err(err_to_string)?;
+    let planned = plan(&api, &selection, &operations, &relations)
+        .await
+        .map_err(err_to_string)?;
+    let reason = reason.unwrap_or_else(|| String::from("Bulk edit"));
+    let mut report = BulkUpdateReport {
+        selected: planned.len(),
+        updated: 0,
+        unchanged: 0,
+        failed: 0,
+        rows: Vec::new(),
+    };
+    for planned in planned {
+        let (before, after, after_relations) = match &planned.edit {
+            Ok(_) if planned.changes.is_empty() => {
+                report.unchanged += 1;
+                continue;
+            }
+            Ok(after) => after,
+            Err(err) => {
+                report.failed += 1;
+                report.rows.push(planned.row(Some(err.to_string())));
+                continue;
+            }
+        };
+        let result = save(
+            &api,
+            &mut relations,
+            before,
+            after,
+            after_relations,
+            &reason,
+        )
+        .await;
+        match result {
+            Ok(()) => report.updated += 1,
+            Err(_) => report.failed += 1,
+        }
+        report
+            .rows
+            .push(planned.row(result.err().map(|err| err.to_string())));
+    }
+    Ok(report)
+}
@@ -0,0 +1,329 @@
+use anyhow::{anyhow, Error};
+use bigdecimal::BigDecimal;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::client::Api;
+use crate::models::{PendingOrder, Product, SupplierItem};
+use crate::quantity::Quantity;
+use crate::{audit, err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppSupplierItem {
+    id: i32,
+    supplier_id: i32,
+    supplier_name: String,
+    product_id: i32,
+    supplier_sku: String,
+    cost_price: String,
+    min_order_quantity: Quantity,
+    case_pack: Option<Quantity>,
+    lead_time_days: i32,
+    preferred: bool,
+}
+
+/// How to pick a supplier when several carry a product.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SupplierChoice {
+    /// The preferred supplier, or the cheapest one if none is preferred.
+    Preferred,
+    Cheapest,
+    Fastest,
+}
+
+impl AppSupplierItem {
+    fn from_item(item: SupplierItem, supplier_name: String) -> Self {
+        AppSupplierItem {
+            id: item.id,
+            supplier_id: item.supplier_id,
+            supplier_name,
+            product_id: item.product_id,
+            supplier_sku: item.supplier_sku,
+            cost_price: item.cost_per_unit.to_string(),
+            min_order_quantity: item.min_order_quantity,
+            case_pack: item.case_pack,
+            lead_time_days: item.lead_time_days,
+            preferred: item.preferred,
+        }
+    }
+    fn to_item(&self) -> Result<SupplierItem, Error> {
+        Ok(SupplierItem {
+            id: self.id,
+            supplier_id: self.supplier_id,
+            product_id: self.product_id,
+            supplier_sku: self.supplier_sku.trim().to_string(),
+            cost_per_unit: BigDecimal::from_str(&self.cost_price)?,
+            min_order_quantity: self.min_order_quantity.clone(),
+            case_pack: self.case_pack.clone(),
+            lead_time_days: self.lead_time_days,
+            preferred: self.preferred,
+        })
+    }
+}
+
+fn check_item(item: &SupplierItem) -> Result<(), Error> {
+    if item.cost_per_unit < BigDecimal::from(0) {
+        return Err(anyhow!("Supplier cost can't be negative"));
+    }
+    if item.min_order_quantity.is_negative() {
+        return Err(anyhow!("Minimum order quantity can't be negative"));
+    }
+    if item
+        .case_pack
+        .as_ref()
+        .map_or(false, |case_pack| !case_pack.is_positive())
+    {
+        return Err(anyhow!("Case pack has to be positive"));
+    }
+    if item.lead_time_days < 0 {
+        return Err(anyhow!("Lead time can't be negative"));
+    }
+    Ok(())
+}
+
+pub fn choose_supplier(items: &[SupplierItem], choice: SupplierChoice) -> Option<&SupplierItem> {
+    let cheapest = || {
+        items
+            .iter()
+            .min_by_key(|item| (&item.cost_per_unit, item.lead_time_days))
+    };
+    match choice {
+        SupplierChoice::Preferred => items.iter().find(|item| item.preferred).or_else(cheapest),
+        SupplierChoice::Cheapest => cheapest(),
+        SupplierChoice::Fastest => items
+            .iter()
+            .min_by_key(|item| (item.lead_time_days, &item.cost_per_unit)),
+    }
+}
+
+/// What to order to bring a product back up to its buy level, raised to the supplier's
+/// minimum and rounded up to whole cases.
+pub fn order_quantity(product: &Product, item: &SupplierItem) -> Quantity {
+    let shortfall = product
+        .buy_level
+        .as_ref()
+        .map(|buy_level| buy_level - &product.amount)
+        .filter(|shortfall| shortfall.is_positive())
+        .unwrap_or_else(Quantity::zero);
+    let quantity = shortfall.max(item.min_order_quantity.clone());
+    match &item.case_pack {
+        Some(case_pack) if case_pack.is_positive() && quantity.is_positive() => {
+            let mut cases = (quantity.decimal() / case_pack.decimal()).with_scale(0);
+            if &cases * case_pack.decimal() < *quantity.decimal() {
+                cases += BigDecimal::from(1);
+            }
+            Quantity::from(cases * case_pack.decimal())
+        }
+        _ => quantity,
+    }
+}
+
+/// A new order for a product, filled in from the given supplier's catalog entry, or from
+/// the one picked by `choice` if no supplier is given.
+pub async fn prefill_order(
+    api: &Api,
+    product_id: i32,
+    supplier_id: Option<i32>,
+    choice: SupplierChoice,
+) -> Result<PendingOrder, Error> {
+    let product = api.get_product(product_id).await?;
+    let items = api.get_product_supplier_items(product_id).await?;
+    let today = Utc::now().date_naive();
+    let item = match supplier_id {
+        Some(supplier_id) => items.iter().find(|item| item.supplier_id == supplier_id),
+        None => choose_supplier(&items, choice),
+    };
+    Ok(PendingOrder {
+        id: 0,
+        product_id,
+        amount: item.map_or_else(Quantity::zero, |item| order_quantity(&product, item)),
+        supplier_id: item.map(|item| item.supplier_id).or(supplier_id),
+        cost_per_unit: item.map(|item| item.cost_per_unit.clone()),
+        ordered: Some(today),
+        expected: item.map(|item| today + Duration::days(item.lead_time_days.into())),
+    })
+}
+
+async fn with_supplier_names(
+    api: &Api,
+    items: Vec<SupplierItem>,
+) -> Result<Vec<AppSupplierItem>, Error> {
+    let mut app_items = Vec::new();
+    for item in items {
+        let name = api.get_supplier(item.supplier_id).await?.name;
+        app_items.push(AppSupplierItem::from_item(item, name));
+    }
+    Ok(app_items)
+}
+
+/// The suppliers carrying a product, cheapest first.
+#[tauri::command]
+pub async fn product_supplier_items(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+) -> Result<Vec<AppSupplierItem>, String> {
+    let api = state.0.lock().await;
+    let mut items = api
+        .get_product_supplier_items(product_id)
+        .await
+        .map_err(err_to_string)?;
+    items.sort_by(|a, b| a.cost_per_unit.cmp(&b.cost_per_unit));
+    with_supplier_names(&api, items)
+        .await
+        .map_err(err_to_string)
+}
+
+#[tauri::command]
+pub async fn supplier_catalog(
+    state: tauri::State<'_, AppState>,
+    supplier_id: i32,
+) -> Result<Vec<AppSupplierItem>, String> {
+    let api = state.0.lock().await;
+    let items = api
+        .get_supplier_items(supplier_id)
+        .await
+        .map_err(err_to_string)?;
+    with_supplier_names(&api, items)
+        .await
+        .map_err(err_to_string)
+}
+
+/// Creates or updates a catalog entry. The supplier is linked to the product if it wasn't
+/// yet, and a preferred entry takes over from the product's previous preferred supplier.
+#[tauri::command]
+pub async fn save_supplier_item(
+    state: tauri::State<'_, AppState>,
+    item: AppSupplierItem,
+) -> Result<AppSupplierItem, String> {
+    let mut item = item.to_item().map_err(err_to_string)?;
+    check_item(&item).map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    let others = api
+        .get_product_supplier_items(item.product_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .filter(|other| other.id != item.id)
+        .collect::<Vec<_>>();
+    if others
+        .iter()
+        .any(|other| other.supplier_id == item.supplier_id)
+    {
+        return Err(String::from(
+            "Supplier already has this product in its catalog",
+        ));
+    }
+    if item.preferred {
+        for mut other in others.into_iter().filter(|other| other.preferred) {
+            other.preferred = false;
+            api.update_supplier_item(&other)
+                .await
+                .map_err(err_to_string)?;
+        }
+    }
+    let mut supplier = api
+        .get_supplier(item.supplier_id)
+        .await
+        .map_err(err_to_string)?;
+    if !supplier.products.contains(&Some(item.product_id)) {
+        supplier.products.push(Some(item.product_id));
+        api.update_supplier(&supplier)
+            .await
+            .map_err(err_to_string)?;
+    }
+    if item.id == 0 {
+        item.id = api.new_supplier_item(&item).await.map_err(err_to_string)?;
+        audit::created(&api, "supplier_item", item.id, &item)
+            .await
+            .map_err(err_to_string)?;
+    } else {
+        let before = api
+            .get_supplier_item(item.id)
+            .await
+            .map_err(err_to_string)?;
+        api.update_supplier_item(&item)
+            .await
+            .map_err(err_to_string)?;
+        audit::updated(&api, "supplier_item", item.id, &before, &item)
+            .await
+            .map_err(err_to_string)?;
+    }
+    Ok(AppSupplierItem::from_item(item, supplier.name))
+}
+
+#[tauri::command]
+pub async fn remove_supplier_item(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let item = api.get_supplier_item(id).await.map_err(err_to_string)?;
+    api.remove_supplier_item(id).await.map_err(err_to_string)?;
+    audit::deleted(&api, "supplier_item", id, &item)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(s: &str) -> Quantity {
+        Quantity::from_str(s).unwrap()
+    }
+
+    fn item(min_order_quantity: &str, case_pack: Option<&str>) -> SupplierItem {
+        SupplierItem {
+            id: 1,
+            supplier_id: 1,
+            product_id: 1,
+            supplier_sku: String::from("SKU-1"),
+            cost_per_unit: BigDecimal::from(2),
+            min_order_quantity: quantity(min_order_quantity),
+            case_pack: case_pack.map(quantity),
+            lead_time_days: 3,
+            preferred: false,
+        }
+    }
+
+    fn stocked(amount: &str, buy_level: Option<&str>) -> Product {
+        Product {
+            amount: quantity(amount),
+            buy_level: buy_level.map(quantity),
+            ..Product::sample(1, "Soap")
+        }
+    }
+
+    #[test]
+    fn orders_the_shortfall() {
+        let order = order_quantity(&stocked("3", Some("10")), &item("0", None));
+        assert_eq!(order, quantity("7"));
+    }
+
+    #[test]
+    fn raises_the_order_to_the_minimum() {
+        let order = order_quantity(&stocked("8", Some("10")), &item("5", None));
+        assert_eq!(order, quantity("5"));
+        let order = order_quantity(&stocked("12", Some("10")), &item("0", None));
+        assert_eq!(order, quantity("0"));
+    }
+
+    #[test]
+    fn rounds_up_to_whole_cases() {
+        let order = order_quantity(&stocked("3", Some("10")), &item("0", Some("6")));
+        assert_eq!(order, quantity("12"));
+        let order = order_quantity(&stocked("4", Some("10")), &item("0", Some("6")));
+        assert_eq!(order, quantity("6"));
+        let order = order_quantity(&stocked("0", Some("1.5")), &item("0", Some("0.5")));
+        assert_eq!(order, quantity("1.5"));
+    }
+
+    #[test]
+    fn orders_nothing_without_a_buy_level() {
+        let order = order_quantity(&stocked("0", None), &item("0", Some("6")));
+        assert_eq!(order, quantity("0"));
+    }
+}
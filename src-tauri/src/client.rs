@@ -7,19 +7,52 @@ use reqwest::{
 };
 
 use chrono::NaiveDateTime;
+use std::future::Future;
 
 use crate::models::*;
+use crate::quantity::Quantity;
 use anyhow::anyhow;
 
 const BASE_URL: &str = "https://d3v3ai4t8a3aev.cloudfront.net/";
+const PAGE_SIZE: i64 = 100;
+
+/// Fetches every page of a paginated list.
+async fn all_pages<T, F, Fut>(mut page: F) -> Result<Vec<T>, anyhow::Error>
+where
+    F: FnMut(i64, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, anyhow::Error>>,
+{
+    let mut items = Vec::new();
+    loop {
+        let next = page(PAGE_SIZE, items.len() as i64).await?;
+        let done = (next.len() as i64) < PAGE_SIZE;
+        items.extend(next);
+        if done {
+            return Ok(items);
+        }
+    }
+}
 
 pub struct Api {
     client: Client,
     headers: HeaderMap,
+    user_name: String,
+    base_url: Url,
 }
 
 impl Api {
     pub async fn new(user_name: &str, password: &str) -> Result<Self, anyhow::Error> {
+        Self::connect(BASE_URL, user_name, password).await
+    }
+
+    /// Logs in to the backend at `base_url`, which can be a local server instead of the
+    /// hosted one.
+    pub async fn connect(
+        base_url: &str,
+        user_name: &str,
+        password: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let base_url = Url::parse(base_url).context("Backend address isn't a valid URL")?;
         let mut headers = HeaderMap::new();
         headers.insert("username", HeaderValue::from_str(user_name)?);
         headers.insert("password", HeaderValue::from_str(password)?);
@@ -27,7 +60,7 @@ impl Api {
 
         client
             .get(Url::parse(
-                Url::parse(BASE_URL)?
+                base_url
                     .join("/initialize/")?
                     .join(&(user_name.to_string() + "/"))?
                     .join(&(password.to_string() + "/"))?
@@ -37,18 +70,31 @@ impl Api {
             .await
             .with_context(|| "can't create")?;
 
-        Ok(Self { client, headers })
+        Ok(Self {
+            client,
+            headers,
+            user_name: user_name.to_string(),
+            base_url,
+        })
+    }
+
+    pub fn user_name(&self) -> &str {
+        &self.user_name
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
     }
 
     pub async fn permissions(&self) -> Result<Permission, anyhow::Error> {
         let thing = self
             .client
-            .get(Url::parse(BASE_URL)?.join("/permissions")?.as_str())
+            .get(self.base_url.join("/permissions")?.as_str())
             .headers(self.headers.clone());
 
         Ok(self
             .client
-            .get(Url::parse(BASE_URL)?.join("/permissions")?.as_str())
+            .get(self.base_url.join("/permissions")?.as_str())
             .headers(self.headers.clone())
             .send()
             .await?
@@ -60,7 +106,7 @@ impl Api {
         let response = self
             .client
             .get(Url::parse_with_params(
-                Url::parse(BASE_URL)?.join("/signup")?.as_str(),
+                self.base_url.join("/signup")?.as_str(),
                 &[
                     ("username", user_name.to_string()),
                     ("password", password.to_string()),
@@ -83,7 +129,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/update_user")?
                         .join(
                             &serde_json::to_string(user)
@@ -104,7 +150,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/update_product/")?
                         .join(
                             &serde_json::to_string(product)
@@ -129,7 +175,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/update_supplier")?.as_str(),
+                    self.base_url.join("/update_supplier")?.as_str(),
                     &[(
                         "supplier_info",
                         serde_json::to_string(supplier)
@@ -149,7 +195,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/update_brand")?.as_str(),
+                    self.base_url.join("/update_brand")?.as_str(),
                     &[(
                         "brand_info",
                         serde_json::to_string(brand).context("Failed to serialize user_info")?,
@@ -168,7 +214,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/update_category")?.as_str(),
+                    self.base_url.join("/update_category")?.as_str(),
                     &[(
                         "category_info",
                         serde_json::to_string(category).context("Failed to serialize user_info")?,
@@ -187,9 +233,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?
-                        .join("/update_pending_order")?
-                        .as_str(),
+                    self.base_url.join("/update_pending_order")?.as_str(),
                     &[(
                         "order_info",
                         serde_json::to_string(order).context("Failed to serialize user_info")?,
@@ -208,9 +252,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?
-                        .join("/update_received_order")?
-                        .as_str(),
+                    self.base_url.join("/update_received_order")?.as_str(),
                     &[(
                         "order_info",
                         serde_json::to_string(order).context("Failed to serialize user_info")?,
@@ -228,7 +270,7 @@ impl Api {
         self.client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/new_brand")?.as_str(),
+                    self.base_url.join("/new_brand")?.as_str(),
                     &[("name", name.to_string())],
                 )
                 .context("Couldn't create url string")?,
@@ -243,13 +285,13 @@ impl Api {
 
     pub async fn new_pending_order(
         &self,
-        amount: f64,
+        amount: Quantity,
         product_id: i32,
     ) -> Result<i32, anyhow::Error> {
         self.client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/new_pending_order")?.as_str(),
+                    self.base_url.join("/new_pending_order")?.as_str(),
                     &[
                         ("amount", amount.to_string()),
                         ("product_id", product_id.to_string()),
@@ -267,7 +309,7 @@ impl Api {
 
     pub async fn product_names(&self) -> Result<Vec<(String, String, i32)>, anyhow::Error> {
         self.client
-            .get(Url::parse(BASE_URL)?.join("/products/names")?.as_str())
+            .get(self.base_url.join("/products/names")?.as_str())
             .headers(self.headers.clone())
             .send()
             .await?
@@ -278,7 +320,7 @@ impl Api {
 
     pub async fn category_names(&self) -> Result<Vec<(String, i32)>, anyhow::Error> {
         self.client
-            .get(Url::parse(BASE_URL)?.join("/categories/names")?.as_str())
+            .get(self.base_url.join("/categories/names")?.as_str())
             .headers(self.headers.clone())
             .send()
             .await?
@@ -289,7 +331,7 @@ impl Api {
 
     pub async fn supplier_names(&self) -> Result<Vec<(String, i32)>, anyhow::Error> {
         self.client
-            .get(Url::parse(BASE_URL)?.join("/suppliers/names")?.as_str())
+            .get(self.base_url.join("/suppliers/names")?.as_str())
             .headers(self.headers.clone())
             .send()
             .await?
@@ -300,7 +342,7 @@ impl Api {
 
     pub async fn brand_names(&self) -> Result<Vec<(String, i32)>, anyhow::Error> {
         self.client
-            .get(Url::parse(BASE_URL)?.join("/brands/names")?.as_str())
+            .get(self.base_url.join("/brands/names")?.as_str())
             .headers(self.headers.clone())
             .send()
             .await?
@@ -313,7 +355,7 @@ impl Api {
         self.client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/new_category")?.as_str(),
+                    self.base_url.join("/new_category")?.as_str(),
                     &[("name", name.to_string())],
                 )
                 .context("Couldn't create url string")?,
@@ -335,7 +377,7 @@ impl Api {
         self.client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/new_supplier")?.as_str(),
+                    self.base_url.join("/new_supplier")?.as_str(),
                     &[
                         ("name", name.to_string()),
                         ("phone_number", phone_number.to_string()),
@@ -360,7 +402,7 @@ impl Api {
         measure_by_weight: bool,
         cost_price_per_unit: BigDecimal,
         selling_price_per_unit: BigDecimal,
-        buy_level: f64,
+        buy_level: Quantity,
         categories: Vec<i32>,
         suppliers: Vec<i32>,
         brand: Option<i32>,
@@ -369,7 +411,7 @@ impl Api {
             self.client
                 .get(
                     Url::parse_with_params(
-                        Url::parse(BASE_URL)?.join("/new_product")?.as_str(),
+                        self.base_url.join("/new_product")?.as_str(),
                         &[
                             ("upc", upc.to_string()),
                             ("name", name.to_string()),
@@ -394,7 +436,7 @@ impl Api {
             self.client
                 .get(
                     Url::parse_with_params(
-                        Url::parse(BASE_URL)?.join("/new_product")?.as_str(),
+                        self.base_url.join("/new_product")?.as_str(),
                         &[
                             ("upc", upc.to_string()),
                             ("name", name.to_string()),
@@ -423,7 +465,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/remove_product/")?
                         .join(&id.to_string())?
                         .as_str(),
@@ -441,7 +483,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/remove_category/")?
                         .join(&id.to_string())?
                         .as_str(),
@@ -459,7 +501,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/remove_brand/")?
                         .join(&id.to_string())?
                         .as_str(),
@@ -477,7 +519,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/remove_supplier/")?
                         .join(&id.to_string())?
                         .as_str(),
@@ -495,7 +537,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/remove_pending_order/")?
                         .join(&id.to_string())?
                         .as_str(),
@@ -513,7 +555,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/remove_received_order/")?
                         .join(&id.to_string())?
                         .as_str(),
@@ -535,7 +577,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/products")?.as_str(),
+                    self.base_url.join("/products")?.as_str(),
                     &[("limit", limit.to_string()), ("offset", offset.to_string())],
                 )
                 .context("Failed to create url string")?,
@@ -556,7 +598,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/pending_orders")?.as_str(),
+                    self.base_url.join("/pending_orders")?.as_str(),
                     &[("limit", limit.to_string()), ("offset", offset.to_string())],
                 )
                 .context("Failed to create url string")?,
@@ -577,7 +619,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/received_orders")?.as_str(),
+                    self.base_url.join("/received_orders")?.as_str(),
                     &[("limit", limit.to_string()), ("offset", offset.to_string())],
                 )
                 .context("Failed to create url string")?,
@@ -594,7 +636,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/brands")?.as_str(),
+                    self.base_url.join("/brands")?.as_str(),
                     &[("limit", limit.to_string()), ("offset", offset.to_string())],
                 )
                 .context("Failed to create url string")?,
@@ -615,7 +657,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/categories")?.as_str(),
+                    self.base_url.join("/categories")?.as_str(),
                     &[("limit", limit.to_string()), ("offset", offset.to_string())],
                 )
                 .context("Failed to create url string")?,
@@ -636,7 +678,7 @@ impl Api {
             .client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?.join("/suppliers")?.as_str(),
+                    self.base_url.join("/suppliers")?.as_str(),
                     &[("limit", limit.to_string()), ("offset", offset.to_string())],
                 )
                 .context("Failed to create url string")?,
@@ -653,7 +695,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/category/")?
                         .join(id.to_string().as_str())?
                         .as_str(),
@@ -672,7 +714,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/supplier/")?
                         .join(id.to_string().as_str())?
                         .as_str(),
@@ -691,7 +733,7 @@ impl Api {
             .client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/product/")?
                         .join(id.to_string().as_str())?
                         .as_str(),
@@ -709,7 +751,7 @@ impl Api {
         self.client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/product_brand/")?
                         .join(product.to_string().as_str())?
                         .as_str(),
@@ -732,7 +774,7 @@ impl Api {
         self.client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/product_suppliers/")?
                         .join(product.to_string().as_str())?
                         .as_str(),
@@ -752,15 +794,13 @@ impl Api {
         &self,
         id: i32,
         date: NaiveDateTime,
-        actually_received: f64,
-        damaged: f64,
+        actually_received: &Quantity,
+        damaged: &Quantity,
     ) -> Result<i32, anyhow::Error> {
         self.client
             .get(
                 Url::parse_with_params(
-                    Url::parse(BASE_URL)?
-                        .join("/mark_order_as_received")?
-                        .as_str(),
+                    self.base_url.join("/mark_order_as_received")?.as_str(),
                     &[
                         ("order_id", id.to_string()),
                         ("date", date.timestamp().to_string()),
@@ -785,7 +825,7 @@ impl Api {
         self.client
             .get(
                 Url::parse(
-                    Url::parse(BASE_URL)?
+                    self.base_url
                         .join("/product_categories/")?
                         .join(product.to_string().as_str())?
                         .as_str(),
@@ -800,4 +840,1189 @@ impl Api {
             .await
             .context("Can't change to json")
     }
+
+    pub async fn get_brand(&self, id: i32) -> Result<Brand, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/brand/")?
+                        .join(id.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn new_price_change(&self, change: &PriceChange) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_price_change")?.as_str(),
+                    &[(
+                        "change_info",
+                        serde_json::to_string(change)
+                            .context("Failed to serialize price change")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn get_price_history(&self, product: i32) -> Result<Vec<PriceChange>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/price_history/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn new_stock_movement(&self, movement: &StockMovement) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_stock_movement")?.as_str(),
+                    &[(
+                        "movement_info",
+                        serde_json::to_string(movement)
+                            .context("Failed to serialize stock movement")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn get_stock_movements(
+        &self,
+        product: i32,
+    ) -> Result<Vec<StockMovement>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/stock_movements/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn all_products(&self) -> Result<Vec<Product>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_products(limit, offset)).await
+    }
+
+    pub async fn all_brands(&self) -> Result<Vec<Brand>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_brands(limit, offset)).await
+    }
+
+    pub async fn all_categories(&self) -> Result<Vec<Category>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_categories(limit, offset)).await
+    }
+
+    pub async fn all_suppliers(&self) -> Result<Vec<Supplier>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_suppliers(limit, offset)).await
+    }
+
+    pub async fn all_pending_orders(&self) -> Result<Vec<PendingOrder>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_pending_orders(limit, offset)).await
+    }
+
+    pub async fn all_count_sessions(&self) -> Result<Vec<CountSession>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_count_sessions(limit, offset)).await
+    }
+
+    pub async fn all_sales(&self) -> Result<Vec<Sale>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_sales(limit, offset)).await
+    }
+
+    pub async fn new_count_session(&self, session: &CountSession) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_count_session")?.as_str(),
+                    &[(
+                        "session_info",
+                        serde_json::to_string(session)
+                            .context("Failed to serialize count session")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_count_session(&self, session: &CountSession) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_count_session")?.as_str(),
+                    &[(
+                        "session_info",
+                        serde_json::to_string(session)
+                            .context("Failed to serialize count session")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_count_session(&self, id: i32) -> Result<CountSession, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/count_session/")?
+                        .join(id.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn get_count_sessions(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<CountSession>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/count_sessions")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn get_locations(&self) -> Result<Vec<Location>, anyhow::Error> {
+        self.client
+            .get(self.base_url.join("/locations")?.as_str())
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn new_location(
+        &self,
+        name: &str,
+        kind: LocationKind,
+        parent_id: Option<i32>,
+    ) -> Result<i32, anyhow::Error> {
+        let mut params = vec![
+            ("name", name.to_string()),
+            ("kind", serde_json::to_string(&kind)?),
+        ];
+        if let Some(parent_id) = parent_id {
+            params.push(("parent_id", parent_id.to_string()));
+        }
+        self.client
+            .get(
+                Url::parse_with_params(self.base_url.join("/new_location")?.as_str(), &params)
+                    .context("Couldn't create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_location(&self, location: &Location) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_location")?.as_str(),
+                    &[(
+                        "location_info",
+                        serde_json::to_string(location).context("Failed to serialize location")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_location(&self, id: i32) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/remove_location/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_product_location_stock(
+        &self,
+        product: i32,
+    ) -> Result<Vec<LocationStock>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/product_location_stock/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_location_stock(
+        &self,
+        location: i32,
+    ) -> Result<Vec<LocationStock>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/location_stock/")?
+                        .join(location.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn update_location_stock(&self, stock: &LocationStock) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_location_stock")?.as_str(),
+                    &[(
+                        "stock_info",
+                        serde_json::to_string(stock)
+                            .context("Failed to serialize location stock")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn new_transfer(&self, transfer: &Transfer) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_transfer")?.as_str(),
+                    &[(
+                        "transfer_info",
+                        serde_json::to_string(transfer).context("Failed to serialize transfer")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_transfer(&self, transfer: &Transfer) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_transfer")?.as_str(),
+                    &[(
+                        "transfer_info",
+                        serde_json::to_string(transfer).context("Failed to serialize transfer")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_transfer(&self, id: i32) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/remove_transfer/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_transfer(&self, id: i32) -> Result<Transfer, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/transfer/")?
+                        .join(id.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn get_transfers(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Transfer>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/transfers")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn all_transfers(&self) -> Result<Vec<Transfer>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_transfers(limit, offset)).await
+    }
+
+    pub async fn new_lot(&self, lot: &Lot) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_lot")?.as_str(),
+                    &[(
+                        "lot_info",
+                        serde_json::to_string(lot).context("Failed to serialize lot")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_lot(&self, lot: &Lot) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_lot")?.as_str(),
+                    &[(
+                        "lot_info",
+                        serde_json::to_string(lot).context("Failed to serialize lot")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_product_lots(&self, product: i32) -> Result<Vec<Lot>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/product_lots/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_lots(&self, limit: i64, offset: i64) -> Result<Vec<Lot>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/lots")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn all_lots(&self) -> Result<Vec<Lot>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_lots(limit, offset)).await
+    }
+
+    pub async fn new_serial(&self, serial: &Serial) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_serial")?.as_str(),
+                    &[(
+                        "serial_info",
+                        serde_json::to_string(serial).context("Failed to serialize serial")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_serial(&self, serial: &Serial) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_serial")?.as_str(),
+                    &[(
+                        "serial_info",
+                        serde_json::to_string(serial).context("Failed to serialize serial")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_product_serials(&self, product: i32) -> Result<Vec<Serial>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/product_serials/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn find_serials(&self, serial: &str) -> Result<Vec<Serial>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/serials")?.as_str(),
+                    &[("serial", serial.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_product_variants(&self, parent: i32) -> Result<Vec<Product>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/product_variants/")?
+                        .join(parent.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn new_supplier_item(
+        &self,
+        supplier_item: &SupplierItem,
+    ) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_supplier_item")?.as_str(),
+                    &[(
+                        "supplier_item_info",
+                        serde_json::to_string(supplier_item)
+                            .context("Failed to serialize supplier item")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_supplier_item(
+        &self,
+        supplier_item: &SupplierItem,
+    ) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_supplier_item")?.as_str(),
+                    &[(
+                        "supplier_item_info",
+                        serde_json::to_string(supplier_item)
+                            .context("Failed to serialize supplier item")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_supplier_item(&self, id: i32) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/remove_supplier_item/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_product_supplier_items(
+        &self,
+        product: i32,
+    ) -> Result<Vec<SupplierItem>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/product_supplier_items/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_supplier_items(
+        &self,
+        supplier: i32,
+    ) -> Result<Vec<SupplierItem>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/supplier_items/")?
+                        .join(supplier.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn all_received_orders(&self) -> Result<Vec<ReceivedOrder>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_received_orders(limit, offset)).await
+    }
+
+    pub async fn new_vendor_return(
+        &self,
+        vendor_return: &VendorReturn,
+    ) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_vendor_return")?.as_str(),
+                    &[(
+                        "vendor_return_info",
+                        serde_json::to_string(vendor_return)
+                            .context("Failed to serialize vendor return")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_vendor_return(
+        &self,
+        vendor_return: &VendorReturn,
+    ) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_vendor_return")?.as_str(),
+                    &[(
+                        "vendor_return_info",
+                        serde_json::to_string(vendor_return)
+                            .context("Failed to serialize vendor return")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_vendor_return(&self, id: i32) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/remove_vendor_return/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_vendor_return(&self, id: i32) -> Result<VendorReturn, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/vendor_return/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_vendor_returns(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<VendorReturn>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/vendor_returns")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn get_received_order(&self, id: i32) -> Result<ReceivedOrder, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/received_order/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn all_vendor_returns(&self) -> Result<Vec<VendorReturn>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_vendor_returns(limit, offset)).await
+    }
+
+    pub async fn new_sale(&self, sale: &Sale) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_sale")?.as_str(),
+                    &[(
+                        "sale_info",
+                        serde_json::to_string(sale).context("Failed to serialize sale")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn get_product_sales(&self, product: i32) -> Result<Vec<Sale>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/product_sales/")?
+                        .join(product.to_string().as_str())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_sales(&self, limit: i64, offset: i64) -> Result<Vec<Sale>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/sales")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn new_pos_import(&self, pos_import: &PosImport) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_pos_import")?.as_str(),
+                    &[(
+                        "pos_import_info",
+                        serde_json::to_string(pos_import)
+                            .context("Failed to serialize pos import")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_pos_import(&self, pos_import: &PosImport) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_pos_import")?.as_str(),
+                    &[(
+                        "pos_import_info",
+                        serde_json::to_string(pos_import)
+                            .context("Failed to serialize pos import")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_pos_imports(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PosImport>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/pos_imports")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    pub async fn all_pos_imports(&self) -> Result<Vec<PosImport>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_pos_imports(limit, offset)).await
+    }
+
+    pub async fn find_pos_imports(&self, hash: &str) -> Result<Vec<PosImport>, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/pos_imports")?.as_str(),
+                    &[("file_hash", hash.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_pending_order(&self, id: i32) -> Result<PendingOrder, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/pending_order/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn get_supplier_item(&self, id: i32) -> Result<SupplierItem, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/supplier_item/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn new_audit_entry(&self, audit_entry: &AuditEntry) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_audit_entry")?.as_str(),
+                    &[(
+                        "audit_entry_info",
+                        serde_json::to_string(audit_entry)
+                            .context("Failed to serialize audit entry")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn get_audit_entries(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditEntry>, anyhow::Error> {
+        let response = self
+            .client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/audit_entries")?.as_str(),
+                    &[("limit", limit.to_string()), ("offset", offset.to_string())],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json();
+        Ok(response.await?)
+    }
+
+    /// The newest audit entry, which the next one is chained to.
+    pub async fn last_audit_entry(&self) -> Result<Option<AuditEntry>, anyhow::Error> {
+        self.client
+            .get(self.base_url.join("/last_audit_entry")?.as_str())
+            .headers(self.headers.clone())
+            .send()
+            .await
+            .context("Can't send request")?
+            .json()
+            .await
+            .context("Can't change to json")
+    }
+
+    pub async fn all_audit_entries(&self) -> Result<Vec<AuditEntry>, anyhow::Error> {
+        all_pages(|limit, offset| self.get_audit_entries(limit, offset)).await
+    }
+
+    pub async fn new_archived_record(
+        &self,
+        archived_record: &ArchivedRecord,
+    ) -> Result<i32, anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/new_archived_record")?.as_str(),
+                    &[(
+                        "archived_record_info",
+                        serde_json::to_string(archived_record)
+                            .context("Failed to serialize archived record")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json::<i32>()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn remove_archived_record(&self, id: i32) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse(
+                    self.base_url
+                        .join("/remove_archived_record/")?
+                        .join(&id.to_string())?
+                        .as_str(),
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_archived_records(&self) -> Result<Vec<ArchivedRecord>, anyhow::Error> {
+        self.client
+            .get(self.base_url.join("/archived_records")?.as_str())
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn get_preference(&self) -> Result<Preference, anyhow::Error> {
+        self.client
+            .get(self.base_url.join("/preference")?.as_str())
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .json()
+            .await
+            .context("Couldn't convert result to json")
+    }
+
+    pub async fn update_preference(&self, preference: &Preference) -> Result<(), anyhow::Error> {
+        self.client
+            .get(
+                Url::parse_with_params(
+                    self.base_url.join("/update_preference")?.as_str(),
+                    &[(
+                        "preference_info",
+                        serde_json::to_string(preference)
+                            .context("Failed to serialize preference")?,
+                    )],
+                )
+                .context("Failed to create url string")?,
+            )
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,408 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::client::Api;
+use crate::models::{
+    AbcClass, AdjustmentReason, ArchivedEntity, CountLine, CountScope, CountSession, CountStatus,
+    Product,
+};
+use crate::quantity::Quantity;
+use crate::recycle_bin::{self, Archived};
+use crate::serials::check_unserialized;
+use crate::stock::{post_movement, value_at_cost};
+use crate::{audit, err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppCountLine {
+    product_id: i32,
+    name: String,
+    upc: String,
+    measure_by_weight: bool,
+    expected: Quantity,
+    counted: Option<Quantity>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppCountSession {
+    id: i32,
+    scope: CountScope,
+    status: CountStatus,
+    user_name: String,
+    created: String,
+    lines: Vec<AppCountLine>,
+}
+
+impl AppCountSession {
+    fn from_session(session: CountSession) -> Self {
+        AppCountSession {
+            id: session.id,
+            scope: session.scope,
+            status: session.status,
+            user_name: session.user_name,
+            created: session.created.format("%m/%d/%Y %H:%M").to_string(),
+            lines: session
+                .lines
+                .into_iter()
+                .map(|line| AppCountLine {
+                    product_id: line.product_id,
+                    name: line.name,
+                    upc: line.upc,
+                    measure_by_weight: line.measure_by_weight,
+                    expected: line.expected,
+                    counted: line.counted,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CountVariance {
+    product_id: i32,
+    name: String,
+    expected: Quantity,
+    counted: Quantity,
+    variance: Quantity,
+    variance_cost: String,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CountVarianceReport {
+    lines: Vec<CountVariance>,
+    total_variance_cost: String,
+}
+
+/// Splits products into ABC classes by stock value at cost: the products making up the
+/// first 80% of the total value are A, the next 15% are B and the rest are C.
+pub fn abc_classes(products: &[Product]) -> HashMap<i32, AbcClass> {
+    let mut values = products
+        .iter()
+        .map(|product| {
+            (
+                product.id,
+                value_at_cost(&product.amount, &product.cost_price_per_unit),
+            )
+        })
+        .collect::<Vec<_>>();
+    values.sort_by(|a, b| b.1.cmp(&a.1));
+    let total = values
+        .iter()
+        .fold(BigDecimal::zero(), |total, (_, value)| total + value);
+
+    let mut running = BigDecimal::zero();
+    values
+        .into_iter()
+        .map(|(id, value)| {
+            let class = if &running * BigDecimal::from(100) < &total * BigDecimal::from(80) {
+                AbcClass::A
+            } else if &running * BigDecimal::from(100) < &total * BigDecimal::from(95) {
+                AbcClass::B
+            } else {
+                AbcClass::C
+            };
+            running += value;
+            (id, class)
+        })
+        .collect()
+}
+
+/// Whether a product has stock of its own to count. Products in the recycle bin aren't
+/// counted, and neither are variant parents, whose stock is kept on their variants.
+fn countable(archived: &Archived, product: &Product) -> bool {
+    !archived.contains(ArchivedEntity::Product, product.id) && product.variant_attributes.is_empty()
+}
+
+/// The products a count covers.
+async fn scoped_products(api: &Api, scope: CountScope) -> Result<Vec<Product>, Error> {
+    let archived = recycle_bin::archived(api).await?;
+    let ids = match scope {
+        CountScope::Category(id) => api.get_category(id).await?.products,
+        CountScope::Brand(id) => api.get_brand(id).await?.products,
+        CountScope::AbcClass(class) => {
+            let products = api
+                .all_products()
+                .await?
+                .into_iter()
+                .filter(|product| countable(&archived, product))
+                .collect::<Vec<_>>();
+            let classes = abc_classes(&products);
+            return Ok(products
+                .into_iter()
+                .filter(|product| classes.get(&product.id) == Some(&class))
+                .collect());
+        }
+    };
+    let mut products = Vec::new();
+    for id in ids.into_iter().flatten() {
+        let product = api.get_product(id).await?;
+        if countable(&archived, &product) {
+            products.push(product);
+        }
+    }
+    Ok(products)
+}
+
+async fn open_session(api: &Api, id: i32) -> Result<CountSession, Error> {
+    let session = api.get_count_session(id).await?;
+    if session.status != CountStatus::Open {
+        return Err(anyhow!("Count session has already been posted"));
+    }
+    Ok(session)
+}
+
+fn variances(session: &CountSession) -> CountVarianceReport {
+    let mut total = BigDecimal::zero();
+    let lines = session
+        .lines
+        .iter()
+        .filter_map(|line| {
+            let counted = line.counted.clone()?;
+            let variance = &counted - &line.expected;
+            let variance_cost = value_at_cost(&variance, &line.cost_price_per_unit);
+            total += &variance_cost;
+            Some(CountVariance {
+                product_id: line.product_id,
+                name: line.name.clone(),
+                expected: line.expected.clone(),
+                counted,
+                variance,
+                variance_cost: variance_cost.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    CountVarianceReport {
+        lines,
+        total_variance_cost: total.to_string(),
+    }
+}
+
+#[tauri::command]
+pub async fn new_count_session(
+    state: tauri::State<'_, AppState>,
+    scope: CountScope,
+) -> Result<AppCountSession, String> {
+    let api = state.0.lock().await;
+    let products = scoped_products(&api, scope).await.map_err(err_to_string)?;
+    let mut session = CountSession {
+        id: 0,
+        scope,
+        status: CountStatus::Open,
+        user_name: api.user_name().to_string(),
+        created: Utc::now().naive_utc(),
+        lines: products
+            .into_iter()
+            .map(|product| CountLine {
+                product_id: product.id,
+                name: product.name,
+                upc: product.upc,
+                measure_by_weight: product.measure_by_weight,
+                cost_price_per_unit: product.cost_price_per_unit,
+                expected: product.amount,
+                counted: None,
+                posted: false,
+            })
+            .collect(),
+    };
+    session.id = api
+        .new_count_session(&session)
+        .await
+        .map_err(err_to_string)?;
+    audit::created(&api, "count_session", session.id, &session)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppCountSession::from_session(session))
+}
+
+#[tauri::command]
+pub async fn get_count_sessions(
+    state: tauri::State<'_, AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AppCountSession>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .get_count_sessions(limit, offset)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(AppCountSession::from_session)
+        .collect())
+}
+
+/// Sets the counted quantity of a product typed in by hand.
+#[tauri::command]
+pub async fn set_count(
+    state: tauri::State<'_, AppState>,
+    session_id: i32,
+    product_id: i32,
+    counted: Quantity,
+) -> Result<AppCountSession, String> {
+    if counted.is_negative() {
+        return Err(String::from("Counted amount can't be negative"));
+    }
+    let api = state.0.lock().await;
+    let mut session = open_session(&api, session_id)
+        .await
+        .map_err(err_to_string)?;
+    let line = session
+        .lines
+        .iter_mut()
+        .find(|line| line.product_id == product_id)
+        .context("Product isn't part of this count")
+        .map_err(err_to_string)?;
+    line.counted = Some(counted);
+    api.update_count_session(&session)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppCountSession::from_session(session))
+}
+
+/// Adds a scanned item to the count. Each scan of a unit product counts one; products
+/// measured by weight need the weight read off the scale with every scan.
+#[tauri::command]
+pub async fn scan_count(
+    state: tauri::State<'_, AppState>,
+    session_id: i32,
+    upc: &str,
+    weight: Option<Quantity>,
+) -> Result<AppCountSession, String> {
+    let api = state.0.lock().await;
+    let mut session = open_session(&api, session_id)
+        .await
+        .map_err(err_to_string)?;
+    let line = session
+        .lines
+        .iter_mut()
+        .find(|line| line.upc == upc)
+        .context("Scanned product isn't part of this count")
+        .map_err(err_to_string)?;
+    let quantity = match (line.measure_by_weight, weight) {
+        (true, Some(weight)) if weight.is_positive() => weight,
+        (true, _) => return Err(String::from("This product is counted by weight")),
+        (false, _) => Quantity::from(1),
+    };
+    line.counted = Some(line.counted.take().unwrap_or_default() + quantity);
+    api.update_count_session(&session)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppCountSession::from_session(session))
+}
+
+#[tauri::command]
+pub async fn count_variances(
+    state: tauri::State<'_, AppState>,
+    session_id: i32,
+) -> Result<CountVarianceReport, String> {
+    Ok(variances(
+        &state
+            .0
+            .lock()
+            .await
+            .get_count_session(session_id)
+            .await
+            .map_err(err_to_string)?,
+    ))
+}
+
+/// Posts the variances of the approved products as stock adjustments and closes the session.
+#[tauri::command]
+pub async fn post_count_session(
+    state: tauri::State<'_, AppState>,
+    session_id: i32,
+    approved: Vec<i32>,
+) -> Result<CountVarianceReport, String> {
+    let api = state.0.lock().await;
+    let mut session = open_session(&api, session_id)
+        .await
+        .map_err(err_to_string)?;
+    let mut posted = session.clone();
+    posted
+        .lines
+        .retain(|line| line.counted.is_some() && approved.contains(&line.product_id));
+    // Checked before anything is posted, so one serialized product doesn't leave half a post.
+    for line in &posted.lines {
+        if !line.posted && line.counted.as_ref() != Some(&line.expected) {
+            check_unserialized(
+                &api.get_product(line.product_id)
+                    .await
+                    .map_err(err_to_string)?,
+            )
+            .map_err(err_to_string)?;
+        }
+    }
+    for index in 0..session.lines.len() {
+        let line = &session.lines[index];
+        if line.posted || !approved.contains(&line.product_id) {
+            continue;
+        }
+        let variance = match &line.counted {
+            Some(counted) => counted - &line.expected,
+            None => continue,
+        };
+        if !variance.is_zero() {
+            post_movement(
+                &api,
+                line.product_id,
+                None,
+                variance,
+                AdjustmentReason::Count,
+                &format!("Count session #{}", session.id),
+            )
+            .await
+            .map_err(err_to_string)?;
+        }
+        // Saved line by line, so a failure part way can be posted again from where it stopped.
+        session.lines[index].posted = true;
+        api.update_count_session(&session)
+            .await
+            .map_err(err_to_string)?;
+    }
+    session.status = CountStatus::Posted;
+    api.update_count_session(&session)
+        .await
+        .map_err(err_to_string)?;
+    Ok(variances(&posted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valued(id: i32, amount: i32, cost: i32) -> Product {
+        Product {
+            amount: Quantity::from(amount),
+            cost_price_per_unit: BigDecimal::from(cost),
+            ..Product::sample(id, "Product")
+        }
+    }
+
+    #[test]
+    fn classes_by_share_of_stock_value() {
+        let products = [
+            valued(1, 10, 70),
+            valued(2, 1, 100),
+            valued(3, 1, 150),
+            valued(4, 5, 10),
+            valued(5, 0, 40),
+        ];
+        let classes = abc_classes(&products);
+        assert_eq!(classes[&1], AbcClass::A);
+        assert_eq!(classes[&3], AbcClass::A);
+        assert_eq!(classes[&2], AbcClass::B);
+        assert_eq!(classes[&4], AbcClass::C);
+        assert_eq!(classes[&5], AbcClass::C);
+    }
+
+    #[test]
+    fn the_most_valuable_product_is_always_a() {
+        let classes = abc_classes(&[valued(1, 1, 1000), valued(2, 1, 1)]);
+        assert_eq!(classes[&1], AbcClass::A);
+        assert_eq!(classes[&2], AbcClass::C);
+        assert!(abc_classes(&[]).is_empty());
+    }
+}
@@ -0,0 +1,491 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::audit;
+use crate::client::Api;
+use crate::history::{Change, Record};
+use crate::models::{ArchivedEntity, KitComponent, PendingOrder, Product, SupplierItem};
+use crate::quantity::Quantity;
+use crate::recycle_bin;
+use crate::relations::Relations;
+use crate::{err_to_string, AppState};
+
+/// What to do with the records that depend on one that's being removed.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Don't remove anything while something depends on it.
+    Block,
+    /// Remove what depends on it too. Products are only taken off a brand, category or
+    /// supplier being removed, not removed themselves.
+    Cascade,
+    /// Point what depends on it at another record of the same kind first.
+    Reassign(i32),
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Resolution::Block
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Dependent {
+    entity: String,
+    id: i32,
+    name: String,
+}
+
+/// Everything that would be left pointing at a record if it were removed.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DeleteImpact {
+    entity: ArchivedEntity,
+    id: i32,
+    dependents: Vec<Dependent>,
+}
+
+/// Records that refer to a brand, category, supplier or product. Products are the ones listed
+/// under a brand, category or supplier; kits and variants are the ones built from a product.
+/// Nothing that's already in the recycle bin counts.
+#[derive(Default)]
+struct Dependents {
+    products: Vec<Product>,
+    pending_orders: Vec<PendingOrder>,
+    supplier_items: Vec<SupplierItem>,
+    kits: Vec<Product>,
+    variants: Vec<Product>,
+}
+
+impl Dependents {
+    fn is_empty(&self) -> bool {
+        self.products.is_empty()
+            && self.pending_orders.is_empty()
+            && self.supplier_items.is_empty()
+            && self.kits.is_empty()
+            && self.variants.is_empty()
+    }
+
+    fn describe(&self) -> Vec<Dependent> {
+        let products = |entity: &str, products: &[Product]| {
+            products
+                .iter()
+                .map(|product| Dependent {
+                    entity: entity.to_string(),
+                    id: product.id,
+                    name: product.name.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut dependents = products("product", &self.products);
+        dependents.extend(self.pending_orders.iter().map(|order| Dependent {
+            entity: String::from("pending_order"),
+            id: order.id,
+            name: format!("Pending order #{}", order.id),
+        }));
+        dependents.extend(self.supplier_items.iter().map(|item| Dependent {
+            entity: String::from("supplier_item"),
+            id: item.id,
+            name: item.supplier_sku.clone(),
+        }));
+        dependents.extend(products("kit", &self.kits));
+        dependents.extend(products("variant", &self.variants));
+        dependents
+    }
+}
+
+async fn dependents(api: &Api, entity: ArchivedEntity, id: i32) -> Result<Dependents, Error> {
+    let archived = recycle_bin::archived(api).await?;
+    let listed = |ids: Vec<Option<i32>>| ids.into_iter().flatten().collect::<HashSet<_>>();
+    let listed = match entity {
+        ArchivedEntity::Brand => listed(api.get_brand(id).await?.products),
+        ArchivedEntity::Category => listed(api.get_category(id).await?.products),
+        ArchivedEntity::Supplier => listed(api.get_supplier(id).await?.products),
+        ArchivedEntity::Product => HashSet::new(),
+        ArchivedEntity::PendingOrder | ArchivedEntity::ReceivedOrder => {
+            return Ok(Dependents::default())
+        }
+    };
+    let products = api
+        .all_products()
+        .await?
+        .into_iter()
+        .filter(|product| !archived.contains(ArchivedEntity::Product, product.id))
+        .collect::<Vec<_>>();
+    let pending_orders = api
+        .all_pending_orders()
+        .await?
+        .into_iter()
+        .filter(|order| !archived.contains(ArchivedEntity::PendingOrder, order.id))
+        .filter(|order| match entity {
+            ArchivedEntity::Supplier => order.supplier_id == Some(id),
+            ArchivedEntity::Product => order.product_id == id,
+            _ => false,
+        })
+        .collect();
+    let supplier_items = match entity {
+        ArchivedEntity::Supplier => api.get_supplier_items(id).await?,
+        _ => Vec::new(),
+    };
+    let mut dependents = Dependents {
+        pending_orders,
+        supplier_items,
+        ..Dependents::default()
+    };
+    for product in products {
+        if listed.contains(&product.id) {
+            dependents.products.push(product);
+        } else if entity == ArchivedEntity::Product {
+            if product
+                .components
+                .iter()
+                .any(|component| component.product_id == id)
+            {
+                dependents.kits.push(product);
+            } else if product.parent_id == Some(id) {
+                dependents.variants.push(product);
+            }
+        }
+    }
+    Ok(dependents)
+}
+
+async fn remove_pending_order(api: &Api, order: &PendingOrder) -> Result<Change, Error> {
+    recycle_bin::archive(
+        api,
+        ArchivedEntity::PendingOrder,
+        order.id,
+        &format!("Pending order #{}", order.id),
+    )
+    .await?;
+    audit::deleted(api, "pending_order", order.id, order).await?;
+    Ok(Change::Deleted(Record::PendingOrder(order.clone())))
+}
+
+/// Takes a product out of any kit that has it.
+async fn remove_from_kit(
+    api: &Api,
+    relations: &Relations,
+    kit: &Product,
+    product_id: i32,
+) -> Result<Change, Error> {
+    let mut after = kit.clone();
+    after
+        .components
+        .retain(|component| component.product_id != product_id);
+    api.update_product(&after).await?;
+    audit::updated(api, "product", kit.id, kit, &after).await?;
+    Ok(updated_product(relations, kit, after))
+}
+
+/// A change to a product that leaves its links as they are.
+fn updated_product(relations: &Relations, before: &Product, after: Product) -> Change {
+    let links = relations.of(before.id);
+    Change::Updated(
+        Record::Product(Box::new(before.clone()), links.clone()),
+        Record::Product(Box::new(after), links),
+    )
+}
+
+/// Puts a product in the recycle bin along with its pending orders, and takes it out of kits.
+async fn remove_product(
+    api: &Api,
+    relations: &Relations,
+    product: &Product,
+) -> Result<Vec<Change>, Error> {
+    let dependents = dependents(api, ArchivedEntity::Product, product.id).await?;
+    let mut changes = Vec::new();
+    for order in &dependents.pending_orders {
+        changes.push(remove_pending_order(api, order).await?);
+    }
+    for kit in &dependents.kits {
+        changes.push(remove_from_kit(api, relations, kit, product.id).await?);
+    }
+    recycle_bin::archive(api, ArchivedEntity::Product, product.id, &product.name).await?;
+    audit::deleted(api, "product", product.id, product).await?;
+    changes.push(Change::Deleted(Record::Product(
+        Box::new(product.clone()),
+        relations.of(product.id),
+    )));
+    Ok(changes)
+}
+
+/// Takes the listed products off a list.
+fn unlist(from: &mut Vec<Option<i32>>, products: &[Product]) {
+    from.retain(|id| !products.iter().any(|product| *id == Some(product.id)));
+}
+
+/// Removes what depends on a record along with it. Products listed under a brand, category
+/// or supplier are only taken off its list, and orders from a supplier no longer name it.
+async fn cascade(
+    api: &Api,
+    entity: ArchivedEntity,
+    id: i32,
+    dependents: Dependents,
+) -> Result<Vec<Change>, Error> {
+    let mut changes = Vec::new();
+    match entity {
+        ArchivedEntity::Brand => {
+            let before = api.get_brand(id).await?;
+            let mut after = before.clone();
+            unlist(&mut after.products, &dependents.products);
+            api.update_brand(&after).await?;
+            audit::updated(api, "brand", id, &before, &after).await?;
+            changes.push(Change::Updated(Record::Brand(before), Record::Brand(after)));
+        }
+        ArchivedEntity::Category => {
+            let before = api.get_category(id).await?;
+            let mut after = before.clone();
+            unlist(&mut after.products, &dependents.products);
+            api.update_category(&after).await?;
+            audit::updated(api, "category", id, &before, &after).await?;
+            changes.push(Change::Updated(
+                Record::Category(before),
+                Record::Category(after),
+            ));
+        }
+        ArchivedEntity::Supplier => {
+            let before = api.get_supplier(id).await?;
+            let mut after = before.clone();
+            unlist(&mut after.products, &dependents.products);
+            api.update_supplier(&after).await?;
+            audit::updated(api, "supplier", id, &before, &after).await?;
+            changes.push(Change::Updated(
+                Record::Supplier(before),
+                Record::Supplier(after),
+            ));
+            for order in &dependents.pending_orders {
+                let after = PendingOrder {
+                    supplier_id: None,
+                    ..order.clone()
+                };
+                api.update_pending_order(&after).await?;
+                audit::updated(api, "pending_order", order.id, order, &after).await?;
+                changes.push(Change::Updated(
+                    Record::PendingOrder(order.clone()),
+                    Record::PendingOrder(after),
+                ));
+            }
+            for item in &dependents.supplier_items {
+                api.remove_supplier_item(item.id).await?;
+                audit::deleted(api, "supplier_item", item.id, item).await?;
+                changes.push(Change::Deleted(Record::SupplierItem(item.clone())));
+            }
+        }
+        ArchivedEntity::Product => {
+            let relations = Relations::load(api).await?;
+            for order in &dependents.pending_orders {
+                changes.push(remove_pending_order(api, order).await?);
+            }
+            for kit in &dependents.kits {
+                changes.push(remove_from_kit(api, &relations, kit, id).await?);
+            }
+            for variant in &dependents.variants {
+                changes.extend(remove_product(api, &relations, variant).await?);
+            }
+        }
+        ArchivedEntity::PendingOrder | ArchivedEntity::ReceivedOrder => {}
+    }
+    Ok(changes)
+}
+
+/// Moves the listed products from one list to another, leaving out any already there.
+fn move_listings(from: &mut Vec<Option<i32>>, to: &mut Vec<Option<i32>>, products: &[Product]) {
+    for product in products {
+        from.retain(|id| *id != Some(product.id));
+        if !to.contains(&Some(product.id)) {
+            to.push(Some(product.id));
+        }
+    }
+}
+
+async fn reassign(
+    api: &Api,
+    entity: ArchivedEntity,
+    id: i32,
+    to: i32,
+    dependents: Dependents,
+) -> Result<Vec<Change>, Error> {
+    if to == id {
+        return Err(anyhow!("Can't reassign a record to itself"));
+    }
+    if recycle_bin::archived(api).await?.contains(entity, to) {
+        return Err(anyhow!("Can't reassign to something in the recycle bin"));
+    }
+    let mut changes = Vec::new();
+    match entity {
+        ArchivedEntity::Brand => {
+            let before = (api.get_brand(id).await?, api.get_brand(to).await?);
+            let (mut from, mut target) = before.clone();
+            move_listings(
+                &mut from.products,
+                &mut target.products,
+                &dependents.products,
+            );
+            api.update_brand(&target).await?;
+            audit::updated(api, "brand", to, &before.1, &target).await?;
+            api.update_brand(&from).await?;
+            audit::updated(api, "brand", id, &before.0, &from).await?;
+            changes.push(Change::Updated(
+                Record::Brand(before.1),
+                Record::Brand(target),
+            ));
+            changes.push(Change::Updated(
+                Record::Brand(before.0),
+                Record::Brand(from),
+            ));
+        }
+        ArchivedEntity::Category => {
+            let before = (api.get_category(id).await?, api.get_category(to).await?);
+            let (mut from, mut target) = before.clone();
+            move_listings(
+                &mut from.products,
+                &mut target.products,
+                &dependents.products,
+            );
+            api.update_category(&target).await?;
+            audit::updated(api, "category", to, &before.1, &target).await?;
+            api.update_category(&from).await?;
+            audit::updated(api, "category", id, &before.0, &from).await?;
+            changes.push(Change::Updated(
+                Record::Category(before.1),
+                Record::Category(target),
+            ));
+            changes.push(Change::Updated(
+                Record::Category(before.0),
+                Record::Category(from),
+            ));
+        }
+        ArchivedEntity::Supplier => {
+            let before = (api.get_supplier(id).await?, api.get_supplier(to).await?);
+            let (mut from, mut target) = before.clone();
+            move_listings(
+                &mut from.products,
+                &mut target.products,
+                &dependents.products,
+            );
+            api.update_supplier(&target).await?;
+            audit::updated(api, "supplier", to, &before.1, &target).await?;
+            api.update_supplier(&from).await?;
+            audit::updated(api, "supplier", id, &before.0, &from).await?;
+            changes.push(Change::Updated(
+                Record::Supplier(before.1),
+                Record::Supplier(target),
+            ));
+            changes.push(Change::Updated(
+                Record::Supplier(before.0),
+                Record::Supplier(from),
+            ));
+            for item in &dependents.supplier_items {
+                let after = SupplierItem {
+                    supplier_id: to,
+                    ..item.clone()
+                };
+                api.update_supplier_item(&after).await?;
+                audit::updated(api, "supplier_item", item.id, item, &after).await?;
+                changes.push(Change::Updated(
+                    Record::SupplierItem(item.clone()),
+                    Record::SupplierItem(after),
+                ));
+            }
+        }
+        ArchivedEntity::Product => {
+            let relations = Relations::load(api).await?;
+            let target = api.get_product(to).await?;
+            for kit in &dependents.kits {
+                let mut after = kit.clone();
+                let moved = after
+                    .components
+                    .iter()
+                    .filter(|component| component.product_id == id)
+                    .fold(Quantity::default(), |amount, component| {
+                        &amount + &component.amount
+                    });
+                after
+                    .components
+                    .retain(|component| component.product_id != id);
+                match after
+                    .components
+                    .iter_mut()
+                    .find(|component| component.product_id == target.id)
+                {
+                    Some(component) => component.amount = &component.amount + &moved,
+                    None => after.components.push(KitComponent {
+                        product_id: target.id,
+                        amount: moved,
+                    }),
+                }
+                api.update_product(&after).await?;
+                audit::updated(api, "product", kit.id, kit, &after).await?;
+                changes.push(updated_product(&relations, kit, after));
+            }
+            for variant in &dependents.variants {
+                let after = Product {
+                    parent_id: Some(target.id),
+                    ..variant.clone()
+                };
+                api.update_product(&after).await?;
+                audit::updated(api, "product", variant.id, variant, &after).await?;
+                changes.push(updated_product(&relations, variant, after));
+            }
+        }
+        ArchivedEntity::PendingOrder | ArchivedEntity::ReceivedOrder => {}
+    }
+    for order in &dependents.pending_orders {
+        let after = match entity {
+            ArchivedEntity::Supplier => PendingOrder {
+                supplier_id: Some(to),
+                ..order.clone()
+            },
+            _ => PendingOrder {
+                product_id: to,
+                ..order.clone()
+            },
+        };
+        api.update_pending_order(&after).await?;
+        audit::updated(api, "pending_order", order.id, order, &after).await?;
+        changes.push(Change::Updated(
+            Record::PendingOrder(order.clone()),
+            Record::PendingOrder(after),
+        ));
+    }
+    Ok(changes)
+}
+
+/// Deals with what depends on a record before it's removed, and returns the changes that
+/// made, so they're undone together with the removal.
+pub async fn resolve(
+    api: &Api,
+    entity: ArchivedEntity,
+    id: i32,
+    resolution: Resolution,
+) -> Result<Vec<Change>, Error> {
+    let dependents = dependents(api, entity, id).await?;
+    if dependents.is_empty() {
+        return Ok(Vec::new());
+    }
+    match resolution {
+        Resolution::Block => Err(anyhow!(
+            "Can't remove this while {} other records depend on it",
+            dependents.describe().len()
+        )),
+        Resolution::Cascade => cascade(api, entity, id, dependents).await,
+        Resolution::Reassign(to) => reassign(api, entity, id, to, dependents).await,
+    }
+}
+
+/// What removing a record would affect, so the user can choose how to deal with it first.
+#[tauri::command]
+pub async fn delete_impact(
+    state: tauri::State<'_, AppState>,
+    entity: ArchivedEntity,
+    id: i32,
+) -> Result<DeleteImpact, String> {
+    let api = state.0.lock().await;
+    let dependents = dependents(&api, entity, id).await.map_err(err_to_string)?;
+    Ok(DeleteImpact {
+        entity,
+        id,
+        dependents: dependents.describe(),
+    })
+}
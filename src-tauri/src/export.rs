@@ -0,0 +1,543 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::client::Api;
+use crate::models::ArchivedEntity;
+use crate::recycle_bin;
+use crate::{err_to_string, AppState};
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportEntity {
+    Products,
+    Suppliers,
+    Brands,
+    Categories,
+    PendingOrders,
+    ReceivedOrders,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Xlsx,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Equals,
+    Contains,
+    GreaterThan,
+    LessThan,
+}
+
+/// Keeps the rows whose `column` matches `value`. Numbers and dates are compared as such,
+/// anything else as text without regard to case. A list column matches if any of its
+/// entries does.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ExportFilter {
+    column: String,
+    op: FilterOp,
+    value: String,
+}
+
+struct Table {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Value>>,
+}
+
+const PRODUCT_COLUMNS: &[&str] = &[
+    "id",
+    "upc",
+    "name",
+    "description",
+    "amount",
+    "base_unit",
+    "case_size",
+    "measure_by_weight",
+    "cost_price",
+    "selling_price",
+    "sale_price",
+    "sale_end",
+    "buy_level",
+    "brand",
+    "categories",
+    "suppliers",
+];
+const SUPPLIER_COLUMNS: &[&str] = &["id", "name", "phone_number", "email", "products"];
+const BRAND_COLUMNS: &[&str] = &["id", "name", "products"];
+const CATEGORY_COLUMNS: &[&str] = &["id", "name", "products"];
+const PENDING_ORDER_COLUMNS: &[&str] = &[
+    "id",
+    "product_id",
+    "product",
+    "upc",
+    "amount",
+    "supplier",
+    "cost_price",
+    "ordered",
+    "expected",
+];
+const RECEIVED_ORDER_COLUMNS: &[&str] = &[
+    "id",
+    "product_id",
+    "product",
+    "upc",
+    "supplier",
+    "gross_amount",
+    "actually_received",
+    "damaged",
+    "ordered",
+    "expected",
+    "received",
+];
+
+impl ExportEntity {
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            ExportEntity::Products => PRODUCT_COLUMNS,
+            ExportEntity::Suppliers => SUPPLIER_COLUMNS,
+            ExportEntity::Brands => BRAND_COLUMNS,
+            ExportEntity::Categories => CATEGORY_COLUMNS,
+            ExportEntity::PendingOrders => PENDING_ORDER_COLUMNS,
+            ExportEntity::ReceivedOrders => RECEIVED_ORDER_COLUMNS,
+        }
+    }
+}
+
+fn number(value: impl ToString) -> Value {
+    let value = value.to_string();
+    Number::from_str(&value)
+        .map(Value::Number)
+        .unwrap_or(Value::String(value))
+}
+
+fn optional<T>(value: Option<T>, to_value: impl FnOnce(T) -> Value) -> Value {
+    value.map(to_value).unwrap_or(Value::Null)
+}
+
+fn date(date: NaiveDate) -> Value {
+    Value::String(date.format("%m/%d/%Y").to_string())
+}
+
+fn time(time: NaiveDateTime) -> Value {
+    Value::String(time.format("%m/%d/%Y %H:%M").to_string())
+}
+
+fn names(names: Option<&Vec<String>>) -> Value {
+    Value::Array(
+        names
+            .into_iter()
+            .flatten()
+            .cloned()
+            .map(Value::String)
+            .collect(),
+    )
+}
+
+/// Which names each product is listed under, from the product lists of brands, categories
+/// or suppliers.
+fn names_by_product<'a>(
+    lists: impl Iterator<Item = (&'a String, &'a Vec<Option<i32>>)>,
+) -> HashMap<i32, Vec<String>> {
+    let mut by_product: HashMap<i32, Vec<String>> = HashMap::new();
+    for (name, products) in lists {
+        for product in products.iter().flatten() {
+            by_product.entry(*product).or_default().push(name.clone());
+        }
+    }
+    by_product
+}
+
+/// Everything but what's in the recycle bin.
+async fn load(api: &Api, entity: ExportEntity) -> Result<Table, Error> {
+    let archived = recycle_bin::archived(api).await?;
+    let rows = match entity {
+        ExportEntity::Products => {
+            let brands = api.all_brands().await?;
+            let brands = names_by_product(
+                brands
+                    .iter()
+                    .filter(|brand| !archived.contains(ArchivedEntity::Brand, brand.id))
+                    .map(|brand| (&brand.name, &brand.products)),
+            );
+            let categories = api.all_categories().await?;
+            let categories = names_by_product(
+                categories
+                    .iter()
+                    .filter(|category| !archived.contains(ArchivedEntity::Category, category.id))
+                    .map(|category| (&category.name, &category.products)),
+            );
+            let suppliers = api.all_suppliers().await?;
+            let suppliers = names_by_product(
+                suppliers
+                    .iter()
+                    .filter(|supplier| !archived.contains(ArchivedEntity::Supplier, supplier.id))
+                    .map(|supplier| (&supplier.name, &supplier.products)),
+            );
+            api.all_products()
+                .await?
+                .into_iter()
+                .filter(|product| !archived.contains(ArchivedEntity::Product, product.id))
+                .map(|product| {
+                    vec![
+                        number(product.id),
+                        Value::String(product.upc),
+                        Value::String(product.name),
+                        Value::String(product.description),
+                        number(product.amount),
+                        serde_json::to_value(product.base_unit).unwrap_or(Value::Null),
+                        optional(product.case_size, number),
+                        Value::Bool(product.measure_by_weight),
+                        number(product.cost_price_per_unit),
+                        number(product.selling_price_per_unit),
+                        optional(product.sale_price, number),
+                        optional(product.sale_end, time),
+                        optional(product.buy_level, number),
+                        optional(
+                            brands.get(&product.id).and_then(|brands| brands.first()),
+                            |brand| Value::String(brand.clone()),
+                        ),
+                        names(categories.get(&product.id)),
+                        names(suppliers.get(&product.id)),
+                    ]
+                })
+                .collect()
+        }
+        ExportEntity::Suppliers => api
+            .all_suppliers()
+            .await?
+            .into_iter()
+            .filter(|supplier| !archived.contains(ArchivedEntity::Supplier, supplier.id))
+            .map(|supplier| {
+                vec![
+                    number(supplier.id),
+                    Value::String(supplier.name),
+                    optional(supplier.phone_number, Value::String),
+                    optional(supplier.email, Value::String),
+                    number(supplier.products.iter().flatten().count()),
+                ]
+            })
+            .collect(),
+        ExportEntity::Brands => api
+            .all_brands()
+            .await?
+            .into_iter()
+            .filter(|brand| !archived.contains(ArchivedEntity::Brand, brand.id))
+            .map(|brand| {
+                vec![
+                    number(brand.id),
+                    Value::String(brand.name),
+                    number(brand.products.iter().flatten().count()),
+                ]
+            })
+            .collect(),
+        ExportEntity::Categories => api
+            .all_categories()
+            .await?
+            .into_iter()
+            .filter(|category| !archived.contains(ArchivedEntity::Category, category.id))
+            .map(|category| {
+                vec![
+                    number(category.id),
+                    Value::String(category.name),
+                    number(category.products.iter().flatten().count()),
+                ]
+            })
+            .collect(),
+        ExportEntity::PendingOrders | ExportEntity::ReceivedOrders => {
+            let products = api
+                .all_products()
+                .await?
+                .into_iter()
+                .map(|product| (product.id, (product.name, product.upc)))
+                .collect::<HashMap<_, _>>();
+            let suppliers = api
+                .all_suppliers()
+                .await?
+                .into_iter()
+                .map(|supplier| (supplier.id, supplier.name))
+                .collect::<HashMap<_, _>>();
+            let product = |id: i32| match products.get(&id) {
+                Some((name, upc)) => (Value::String(name.clone()), Value::String(upc.clone())),
+                None => (Value::Null, Value::Null),
+            };
+            let supplier = |id: Option<i32>| {
+                optional(id.and_then(|id| suppliers.get(&id)), |name| {
+                    Value::String(name.clone())
+                })
+            };
+            if entity == ExportEntity::PendingOrders {
+                api.all_pending_orders()
+                    .await?
+                    .into_iter()
+                    .filter(|order| !archived.contains(ArchivedEntity::PendingOrder, order.id))
+                    .map(|order| {
+                        let (name, upc) = product(order.product_id);
+                        vec![
+                            number(order.id),
+                            number(order.product_id),
+                            name,
+                            upc,
+                            number(order.amount),
+                            supplier(order.supplier_id),
+                            optional(order.cost_per_unit, number),
+                            optional(order.ordered, date),
+                            optional(order.expected, date),
+                        ]
+                    })
+                    .collect()
+            } else {
+                api.all_received_orders()
+                    .await?
+                    .into_iter()
+                    .filter(|order| !archived.contains(ArchivedEntity::ReceivedOrder, order.id))
+                    .map(|order| {
+                        let (name, upc) = product(order.product_id);
+                        vec![
+                            number(order.id),
+                            number(order.product_id),
+                            name,
+                            upc,
+                            supplier(order.supplier_id),
+                            number(order.gross_amount),
+                            number(order.actually_received),
+                            number(order.damaged),
+                            optional(order.ordered, date),
+                            optional(order.expected, date),
+                            optional(order.received, time),
+                        ]
+                    })
+                    .collect()
+            }
+        }
+    };
+    Ok(Table {
+        columns: entity.columns(),
+        rows,
+    })
+}
+
+/// A cell as it's written to a CSV file or a spreadsheet. Lists are joined with commas.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        Value::Array(values) => values.iter().map(text).collect::<Vec<_>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%m/%d/%Y %H:%M")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%m/%d/%Y")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+fn compare(cell: &str, value: &str) -> Ordering {
+    if let (Ok(cell), Ok(value)) = (BigDecimal::from_str(cell), BigDecimal::from_str(value)) {
+        return cell.cmp(&value);
+    }
+    if let (Some(cell), Some(value)) = (parse_date(cell), parse_date(value)) {
+        return cell.cmp(&value);
+    }
+    cell.to_lowercase().cmp(&value.to_lowercase())
+}
+
+impl ExportFilter {
+    fn matches(&self, cell: &Value) -> bool {
+        if let Value::Array(values) = cell {
+            return values.iter().any(|value| self.matches(value));
+        }
+        let cell = text(cell);
+        let value = self.value.trim();
+        match self.op {
+            FilterOp::Equals => compare(&cell, value) == Ordering::Equal,
+            FilterOp::Contains => cell.to_lowercase().contains(&value.to_lowercase()),
+            FilterOp::GreaterThan => !cell.is_empty() && compare(&cell, value) == Ordering::Greater,
+            FilterOp::LessThan => !cell.is_empty() && compare(&cell, value) == Ordering::Less,
+        }
+    }
+}
+
+fn column_index(table: &Table, name: &str) -> Result<usize, Error> {
+    table
+        .columns
+        .iter()
+        .position(|column| column.eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| {
+            anyhow!(
+                "There's no {} column, only {}",
+                name,
+                table.columns.join(", ")
+            )
+        })
+}
+
+/// The rows that pass every filter, cut down to the chosen columns in the chosen order.
+fn select(
+    table: Table,
+    columns: Option<Vec<String>>,
+    filters: &[ExportFilter],
+) -> Result<(Vec<&'static str>, Vec<Vec<Value>>), Error> {
+    let filters = filters
+        .iter()
+        .map(|filter| Ok((column_index(&table, &filter.column)?, filter)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let indexes = match columns {
+        Some(columns) if !columns.is_empty() => columns
+            .iter()
+            .map(|column| column_index(&table, column))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => (0..table.columns.len()).collect(),
+    };
+    let rows = table
+        .rows
+        .into_iter()
+        .filter(|row| {
+            filters
+                .iter()
+                .all(|(index, filter)| filter.matches(&row[*index]))
+        })
+        .map(|row| indexes.iter().map(|index| row[*index].clone()).collect())
+        .collect();
+    Ok((
+        indexes.iter().map(|index| table.columns[*index]).collect(),
+        rows,
+    ))
+}
+
+fn write_csv(path: &str, columns: &[&str], rows: &[Vec<Value>]) -> Result<(), Error> {
+    let mut writer =
+        csv::Writer::from_path(path).with_context(|| format!("Can't write {}", path))?;
+    writer.write_record(columns)?;
+    for row in rows {
+        writer.write_record(row.iter().map(text))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_json(path: &str, columns: &[&str], rows: &[Vec<Value>]) -> Result<(), Error> {
+    let objects = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| column.to_string())
+                .zip(row.iter().cloned())
+                .collect::<Map<_, _>>()
+        })
+        .collect::<Vec<_>>();
+    std::fs::write(path, serde_json::to_string_pretty(&objects)?)
+        .with_context(|| format!("Can't write {}", path))
+}
+
+fn write_xlsx(path: &str, sheet: &str, columns: &[&str], rows: &[Vec<Value>]) -> Result<(), Error> {
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet)?;
+    let header = Format::new().set_bold();
+    for (column, name) in columns.iter().enumerate() {
+        worksheet.write_string_with_format(0, column as u16, *name, &header)?;
+    }
+    for (row, values) in rows.iter().enumerate() {
+        let row = row as u32 + 1;
+        for (column, value) in values.iter().enumerate() {
+            let column = column as u16;
+            match value {
+                Value::Null => {}
+                Value::Bool(value) => {
+                    worksheet.write_boolean(row, column, *value)?;
+                }
+                Value::Number(number) => match number.as_f64() {
+                    Some(number) => {
+                        worksheet.write_number(row, column, number)?;
+                    }
+                    None => {
+                        worksheet.write_string(row, column, number.to_string())?;
+                    }
+                },
+                value => {
+                    worksheet.write_string(row, column, text(value))?;
+                }
+            }
+        }
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofit();
+    workbook
+        .save(path)
+        .with_context(|| format!("Can't write {}", path))
+}
+
+/// The columns an entity can be exported with, in their default order.
+#[tauri::command]
+pub async fn export_columns(entity: ExportEntity) -> Result<Vec<String>, String> {
+    Ok(entity
+        .columns()
+        .iter()
+        .map(|column| column.to_string())
+        .collect())
+}
+
+/// The ids of the products that pass every filter, for commands that act on a filtered list.
+pub async fn product_ids(api: &Api, filters: &[ExportFilter]) -> Result<Vec<i32>, Error> {
+    let table = load(api, ExportEntity::Products).await?;
+    let (_, rows) = select(table, Some(vec![String::from("id")]), filters)?;
+    rows.iter()
+        .map(|row| {
+            row[0]
+                .as_i64()
+                .map(|id| id as i32)
+                .ok_or_else(|| anyhow!("Product id isn't a number"))
+        })
+        .collect()
+}
+
+/// Writes a list to a file and returns how many rows it has. Leaving out `columns` exports
+/// all of them. Products come with the names of their brand, categories and suppliers, and
+/// orders with the names of their product and supplier.
+#[tauri::command]
+pub async fn export_entities(
+    state: tauri::State<'_, AppState>,
+    entity: ExportEntity,
+    format: ExportFormat,
+    path: String,
+    columns: Option<Vec<String>>,
+    filters: Option<Vec<ExportFilter>>,
+) -> Result<usize, String> {
+    let table = load(&*state.0.lock().await, entity)
+        .await
+        .map_err(err_to_string)?;
+    let (columns, rows) =
+        select(table, columns, &filters.unwrap_or_default()).map_err(err_to_string)?;
+    match format {
+        ExportFormat::Csv => write_csv(&path, &columns, &rows),
+        ExportFormat::Json => write_json(&path, &columns, &rows),
+        ExportFormat::Xlsx => {
+            let sheet = serde_json::to_value(entity)
+                .ok()
+                .as_ref()
+                .map(text)
+                .unwrap_or_default();
+            write_xlsx(&path, &sheet, &columns, &rows)
+        }
+    }
+    .map_err(err_to_string)?;
+    Ok(rows.len())
+}
@@ -0,0 +1,345 @@
+use anyhow::Error;
+use futures::lock::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::audit;
+use crate::client::Api;
+use crate::dependents::{self, Resolution};
+use crate::models::{
+    ArchivedEntity, Brand, Category, PendingOrder, Product, ReceivedOrder, Supplier, SupplierItem,
+};
+use crate::pricing;
+use crate::recycle_bin;
+use crate::relations::{ProductRelations, Relations};
+use crate::{err_to_string, AppState};
+
+/// How many changes can be undone. Older ones are forgotten.
+const HISTORY_LIMIT: usize = 50;
+
+/// A record as it was at some point. A product keeps what it was listed under, since its
+/// links are saved on the brands, categories and suppliers.
+#[derive(Clone)]
+pub enum Record {
+    Brand(Brand),
+    Category(Category),
+    Supplier(Supplier),
+    Product(Box<Product>, ProductRelations),
+    PendingOrder(PendingOrder),
+    ReceivedOrder(ReceivedOrder),
+    SupplierItem(SupplierItem),
+}
+
+#[derive(Clone)]
+pub enum Change {
+    Created(Record),
+    Updated(Record, Record),
+    Deleted(Record),
+}
+
+/// Changes made this session that can be undone, and undone ones that can be redone. Changes
+/// made together, like a delete and what it cascaded to, are one entry.
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+}
+
+#[derive(Default)]
+pub struct HistoryState(Mutex<History>);
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct HistoryStatus {
+    undo: Option<String>,
+    redo: Option<String>,
+    undo_count: usize,
+    redo_count: usize,
+}
+
+impl Record {
+    fn entity(&self) -> &'static str {
+        match self {
+            Record::Brand(_) => "brand",
+            Record::Category(_) => "category",
+            Record::Supplier(_) => "supplier",
+            Record::Product(..) => "product",
+            Record::PendingOrder(_) => "pending_order",
+            Record::ReceivedOrder(_) => "received_order",
+            Record::SupplierItem(_) => "supplier_item",
+        }
+    }
+
+    fn id(&self) -> i32 {
+        match self {
+            Record::Brand(brand) => brand.id,
+            Record::Category(category) => category.id,
+            Record::Supplier(supplier) => supplier.id,
+            Record::Product(product, _) => product.id,
+            Record::PendingOrder(order) => order.id,
+            Record::ReceivedOrder(order) => order.id,
+            Record::SupplierItem(item) => item.id,
+        }
+    }
+}
+
+impl Change {
+    fn describe(&self) -> String {
+        let (verb, record) = match self {
+            Change::Created(record) => ("Create", record),
+            Change::Updated(_, record) => ("Edit", record),
+            Change::Deleted(record) => ("Delete", record),
+        };
+        format!(
+            "{} {} #{}",
+            verb,
+            record.entity().replace('_', " "),
+            record.id()
+        )
+    }
+}
+
+/// Adds a change to the history. Anything that was undone can't be redone after this.
+pub async fn record(history: &HistoryState, change: Change) {
+    record_all(history, vec![change]).await
+}
+
+/// Adds changes made together to the history, to be undone and redone as one. The last of
+/// them is the one the user asked for.
+pub async fn record_all(history: &HistoryState, changes: Vec<Change>) {
+    let mut history = history.0.lock().await;
+    history.redo.clear();
+    history.undo.push_back(changes);
+    if history.undo.len() > HISTORY_LIMIT {
+        history.undo.pop_front();
+    }
+}
+
+/// Forgets everything, such as when another user logs in.
+pub async fn clear(history: &HistoryState) {
+    *history.0.lock().await = History::default();
+}
+
+impl History {
+    fn status(&self) -> HistoryStatus {
+        HistoryStatus {
+            undo: self
+                .undo
+                .back()
+                .and_then(|changes| changes.last())
+                .map(Change::describe),
+            redo: self
+                .redo
+                .last()
+                .and_then(|changes| changes.last())
+                .map(Change::describe),
+            undo_count: self.undo.len(),
+            redo_count: self.redo.len(),
+        }
+    }
+}
+
+impl Record {
+    /// Supplier items don't go to the recycle bin, they're removed and made again.
+    fn archived(&self) -> Option<ArchivedEntity> {
+        match self {
+            Record::Brand(_) => Some(ArchivedEntity::Brand),
+            Record::Category(_) => Some(ArchivedEntity::Category),
+            Record::Supplier(_) => Some(ArchivedEntity::Supplier),
+            Record::Product(..) => Some(ArchivedEntity::Product),
+            Record::PendingOrder(_) => Some(ArchivedEntity::PendingOrder),
+            Record::ReceivedOrder(_) => Some(ArchivedEntity::ReceivedOrder),
+            Record::SupplierItem(_) => None,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Record::Brand(brand) => brand.name.clone(),
+            Record::Category(category) => category.name.clone(),
+            Record::Supplier(supplier) => supplier.name.clone(),
+            Record::Product(product, _) => product.name.clone(),
+            Record::PendingOrder(order) => format!("Pending order #{}", order.id),
+            Record::ReceivedOrder(order) => format!("Received order #{}", order.id),
+            Record::SupplierItem(item) => item.supplier_sku.clone(),
+        }
+    }
+
+    fn audit_json(&self) -> Result<serde_json::Value, Error> {
+        Ok(match self {
+            Record::Brand(brand) => serde_json::to_value(brand)?,
+            Record::Category(category) => serde_json::to_value(category)?,
+            Record::Supplier(supplier) => serde_json::to_value(supplier)?,
+            Record::Product(product, _) => serde_json::to_value(product)?,
+            Record::PendingOrder(order) => serde_json::to_value(order)?,
+            Record::ReceivedOrder(order) => serde_json::to_value(order)?,
+            Record::SupplierItem(item) => serde_json::to_value(item)?,
+        })
+    }
+}
+
+/// Puts a record in the recycle bin, as long as nothing depends on it.
+async fn delete(api: &Api, record: &Record) -> Result<(), Error> {
+    match record.archived() {
+        Some(entity) => {
+            dependents::resolve(api, entity, record.id(), Resolution::Block).await?;
+            recycle_bin::archive(api, entity, record.id(), &record.name()).await?;
+        }
+        None => api.remove_supplier_item(record.id()).await?,
+    }
+    audit::deleted(api, record.entity(), record.id(), &record.audit_json()?).await
+}
+
+/// Takes a record back out of the recycle bin. A supplier item is made again, under a new id.
+async fn restore(api: &Api, record: &mut Record) -> Result<(), Error> {
+    if let Record::SupplierItem(item) = record {
+        item.id = api.new_supplier_item(item).await?;
+    } else if let Some(entity) = record.archived() {
+        recycle_bin::unarchive(api, entity, record.id()).await?;
+    }
+    audit::created(api, record.entity(), record.id(), &record.audit_json()?).await
+}
+
+/// Saves a record as it was. Stock isn't part of this, since it only moves through the
+/// ledger, so a product keeps the amount it has now. Its links are put back too, and a
+/// price it gets back goes on its price history with the reason given.
+async fn save(api: &Api, record: &Record, reason: &str) -> Result<(), Error> {
+    let id = record.id();
+    match record {
+        Record::Brand(brand) => {
+            let before = api.get_brand(id).await?;
+            api.update_brand(brand).await?;
+            audit::updated(api, "brand", id, &before, brand).await
+        }
+        Record::Category(category) => {
+            let before = api.get_category(id).await?;
+            api.update_category(category).await?;
+            audit::updated(api, "category", id, &before, category).await
+        }
+        Record::Supplier(supplier) => {
+            let before = api.get_supplier(id).await?;
+            api.update_supplier(supplier).await?;
+            audit::updated(api, "supplier", id, &before, supplier).await
+        }
+        Record::Product(product, relations) => {
+            let before = api.get_product(id).await?;
+            let product = Product {
+                amount: before.amount.clone(),
+                ..(**product).clone()
+            };
+            api.update_product(&product).await?;
+            if let Err(err) = Relations::load(api).await?.save(api, id, relations).await {
+                api.update_product(&before).await?;
+                return Err(err);
+            }
+            pricing::record_price_change(api, &before, &product, reason).await?;
+            audit::updated(api, "product", id, &before, &product).await
+        }
+        Record::PendingOrder(order) => {
+            let before = api.get_pending_order(id).await?;
+            api.update_pending_order(order).await?;
+            audit::updated(api, "pending_order", id, &before, order).await
+        }
+        Record::ReceivedOrder(order) => {
+            let before = api.get_received_order(id).await?;
+            api.update_received_order(order).await?;
+            audit::updated(api, "received_order", id, &before, order).await
+        }
+        Record::SupplierItem(item) => {
+            let before = api.get_supplier_item(id).await?;
+            api.update_supplier_item(item).await?;
+            audit::updated(api, "supplier_item", id, &before, item).await
+        }
+    }
+}
+
+/// Carries out a change one way or the other.
+async fn apply(api: &Api, change: &mut Change, undo: bool) -> Result<(), Error> {
+    match (change, undo) {
+        (Change::Created(record), true) | (Change::Deleted(record), false) => {
+            delete(api, record).await
+        }
+        (Change::Created(record), false) | (Change::Deleted(record), true) => {
+            restore(api, record).await
+        }
+        (Change::Updated(before, _), true) => save(api, before, "Undo").await,
+        (Change::Updated(_, after), false) => save(api, after, "Redo").await,
+    }
+}
+
+#[tauri::command]
+pub async fn history_status(
+    history: tauri::State<'_, HistoryState>,
+) -> Result<HistoryStatus, String> {
+    Ok(history.0.lock().await.status())
+}
+
+/// Carries out the changes of an entry, the last first when undoing. The ones carried out
+/// are taken off the entry and returned, so if one fails the rest can be tried again
+/// without repeating them.
+async fn apply_entry(
+    api: &Api,
+    changes: &mut Vec<Change>,
+    undo: bool,
+) -> (Vec<Change>, Result<(), Error>) {
+    let mut done = Vec::new();
+    while !changes.is_empty() {
+        let index = if undo { changes.len() - 1 } else { 0 };
+        let mut change = changes.remove(index);
+        if let Err(err) = apply(api, &mut change, undo).await {
+            changes.insert(index, change);
+            return (done, Err(err));
+        }
+        if undo {
+            done.insert(0, change);
+        } else {
+            done.push(change);
+        }
+    }
+    (done, Ok(()))
+}
+
+/// Reverses the last change. If that fails what's left of it stays on the history.
+#[tauri::command]
+pub async fn undo(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, HistoryState>,
+) -> Result<HistoryStatus, String> {
+    let api = state.0.lock().await;
+    let mut history = history.0.lock().await;
+    let mut changes = history
+        .undo
+        .pop_back()
+        .ok_or_else(|| String::from("Nothing to undo"))?;
+    let (done, result) = apply_entry(&api, &mut changes, true).await;
+    if !done.is_empty() {
+        history.redo.push(done);
+    }
+    if let Err(err) = result {
+        history.undo.push_back(changes);
+        return Err(err_to_string(err));
+    }
+    Ok(history.status())
+}
+
+/// Makes the last undone change again.
+#[tauri::command]
+pub async fn redo(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, HistoryState>,
+) -> Result<HistoryStatus, String> {
+    let api = state.0.lock().await;
+    let mut history = history.0.lock().await;
+    let mut changes = history
+        .redo
+        .pop()
+        .ok_or_else(|| String::from("Nothing to redo"))?;
+    let (done, result) = apply_entry(&api, &mut changes, false).await;
+    if !done.is_empty() {
+        history.undo.push_back(done);
+    }
+    if let Err(err) = result {
+        history.redo.push(changes);
+        return Err(err_to_string(err));
+    }
+    Ok(history.status())
+}
@@ -0,0 +1,331 @@
+use anyhow::{anyhow, Error};
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::audit;
+use crate::client::Api;
+use crate::models::{AdjustmentReason, KitComponent, Product};
+use crate::pricing::record_price_change;
+use crate::quantity::Quantity;
+use crate::stock::post_movement;
+use crate::{err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppKitComponent {
+    product_id: i32,
+    name: String,
+    amount: Quantity,
+    available: Quantity,
+    cost_price: String,
+}
+
+/// How many of a kit are assembled and how many more the component stock could make.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KitAvailability {
+    kit_id: i32,
+    name: String,
+    on_hand: Quantity,
+    buildable: Quantity,
+    cost_price: String,
+    components: Vec<AppKitComponent>,
+}
+
+/// Stock of a product at a location, or everywhere if no location is given.
+async fn available(
+    api: &Api,
+    product: &Product,
+    location_id: Option<i32>,
+) -> Result<Quantity, Error> {
+    match location_id {
+        Some(location_id) => Ok(api
+            .get_product_location_stock(product.id)
+            .await?
+            .into_iter()
+            .find(|stock| stock.location_id == location_id)
+            .map_or_else(Quantity::zero, |stock| stock.amount)),
+        None => Ok(product.amount.clone()),
+    }
+}
+
+async fn component_products(api: &Api, kit: &Product) -> Result<Vec<Product>, Error> {
+    let mut products = Vec::new();
+    for component in &kit.components {
+        products.push(api.get_product(component.product_id).await?);
+    }
+    Ok(products)
+}
+
+/// The cost of one kit, which is the cost of everything in it rounded to whole cents.
+fn kit_cost(components: &[KitComponent], products: &[Product]) -> BigDecimal {
+    components
+        .iter()
+        .zip(products)
+        .map(|(component, product)| component.amount.decimal() * &product.cost_price_per_unit)
+        .fold(BigDecimal::zero(), |total, cost| total + cost)
+        .round(2)
+}
+
+/// How many whole kits the available component stock makes.
+fn buildable(components: &[KitComponent], available: &[Quantity]) -> Quantity {
+    components
+        .iter()
+        .zip(available)
+        .map(|(component, available)| {
+            if available.is_positive() {
+                Quantity::from((available.decimal() / component.amount.decimal()).with_scale(0))
+            } else {
+                Quantity::zero()
+            }
+        })
+        .min()
+        .unwrap_or_else(Quantity::zero)
+}
+
+async fn availability(
+    api: &Api,
+    kit: Product,
+    location_id: Option<i32>,
+) -> Result<KitAvailability, Error> {
+    let products = component_products(api, &kit).await?;
+    let mut stock = Vec::new();
+    for product in &products {
+        stock.push(available(api, product, location_id).await?);
+    }
+    Ok(KitAvailability {
+        kit_id: kit.id,
+        on_hand: available(api, &kit, location_id).await?,
+        buildable: buildable(&kit.components, &stock),
+        cost_price: kit.cost_price_per_unit.to_string(),
+        components: kit
+            .components
+            .iter()
+            .zip(products)
+            .zip(stock)
+            .map(|((component, product), available)| AppKitComponent {
+                product_id: product.id,
+                name: product.name,
+                amount: component.amount.clone(),
+                available,
+                cost_price: product.cost_price_per_unit.to_string(),
+            })
+            .collect(),
+        name: kit.name,
+    })
+}
+
+async fn get_kit(api: &Api, kit_id: i32) -> Result<Product, Error> {
+    let kit = api.get_product(kit_id).await?;
+    if kit.components.is_empty() {
+        return Err(anyhow!("{} isn't a kit", kit.name));
+    }
+    Ok(kit)
+}
+
+fn check_kit_quantity(quantity: &Quantity) -> Result<(), Error> {
+    if !quantity.is_positive() || !quantity.is_whole() {
+        return Err(anyhow!("Kits are assembled in whole units"));
+    }
+    Ok(())
+}
+
+/// Sets a kit's cost to what its components cost now.
+async fn roll_up_cost(api: &Api, kit: &mut Product, reason: &str) -> Result<(), Error> {
+    let products = component_products(api, kit).await?;
+    let before = kit.clone();
+    kit.cost_price_per_unit = kit_cost(&kit.components, &products);
+    if kit.cost_price_per_unit != before.cost_price_per_unit {
+        api.update_product(kit).await?;
+        record_price_change(api, &before, kit, reason).await?;
+        audit::updated(api, "product", kit.id, &before, kit).await?;
+    }
+    Ok(())
+}
+
+/// Turns a product into a kit made of the given components, or changes what a kit is made
+/// of. Passing no components turns a kit without stock back into a plain product.
+#[tauri::command]
+pub async fn set_kit_components(
+    state: tauri::State<'_, AppState>,
+    kit_id: i32,
+    components: Vec<KitComponent>,
+) -> Result<Option<KitAvailability>, String> {
+    let api = state.0.lock().await;
+    let mut kit = api.get_product(kit_id).await.map_err(err_to_string)?;
+    if kit.track_serials || !kit.variant_attributes.is_empty() {
+        return Err(format!("{} can't be a kit", kit.name));
+    }
+    if components.is_empty() {
+        if !kit.components.is_empty() && !kit.amount.is_zero() {
+            return Err(format!("Disassemble the stock of {} first", kit.name));
+        }
+        let before = kit.clone();
+        kit.components = Vec::new();
+        api.update_product(&kit).await.map_err(err_to_string)?;
+        audit::updated(&api, "product", kit_id, &before, &kit)
+            .await
+            .map_err(err_to_string)?;
+        return Ok(None);
+    }
+    if kit.components.is_empty() && !kit.amount.is_zero() {
+        return Err(format!(
+            "{} still has stock, adjust it to zero before making it a kit",
+            kit.name
+        ));
+    }
+    let mut seen = HashSet::new();
+    for component in &components {
+        if !component.amount.is_positive() {
+            return Err(String::from("Component amounts have to be positive"));
+        }
+        if component.product_id == kit_id {
+            return Err(String::from("A kit can't contain itself"));
+        }
+        if !seen.insert(component.product_id) {
+            return Err(format!(
+                "Product #{} was entered twice",
+                component.product_id
+            ));
+        }
+        let product = api
+            .get_product(component.product_id)
+            .await
+            .map_err(err_to_string)?;
+        if !product.components.is_empty()
+            || !product.variant_attributes.is_empty()
+            || product.track_serials
+        {
+            return Err(format!("{} can't be part of a kit", product.name));
+        }
+    }
+    let before = kit.clone();
+    kit.components = components;
+    api.update_product(&kit).await.map_err(err_to_string)?;
+    audit::updated(&api, "product", kit_id, &before, &kit)
+        .await
+        .map_err(err_to_string)?;
+    roll_up_cost(&api, &mut kit, "Kit components changed")
+        .await
+        .map_err(err_to_string)?;
+    Ok(Some(
+        availability(&api, kit, None).await.map_err(err_to_string)?,
+    ))
+}
+
+#[tauri::command]
+pub async fn kit_availability(
+    state: tauri::State<'_, AppState>,
+    kit_id: i32,
+    location_id: Option<i32>,
+) -> Result<KitAvailability, String> {
+    let api = state.0.lock().await;
+    let kit = get_kit(&api, kit_id).await.map_err(err_to_string)?;
+    availability(&api, kit, location_id)
+        .await
+        .map_err(err_to_string)
+}
+
+/// Takes the components of `quantity` kits out of stock and puts the kits in. The kit's
+/// cost is rolled up from what its components cost now.
+#[tauri::command]
+pub async fn assemble_kit(
+    state: tauri::State<'_, AppState>,
+    kit_id: i32,
+    quantity: Quantity,
+    location_id: Option<i32>,
+) -> Result<KitAvailability, String> {
+    check_kit_quantity(&quantity).map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    let mut kit = get_kit(&api, kit_id).await.map_err(err_to_string)?;
+    let products = component_products(&api, &kit)
+        .await
+        .map_err(err_to_string)?;
+    for (component, product) in kit.components.iter().zip(&products) {
+        let needed = Quantity::from(component.amount.decimal() * quantity.decimal());
+        let available = available(&api, product, location_id)
+            .await
+            .map_err(err_to_string)?;
+        if available < needed {
+            return Err(format!(
+                "Need {} of {} but only {} are available",
+                needed, product.name, available
+            ));
+        }
+    }
+    let note = format!("Assembled {} x {}", quantity, kit.name);
+    for component in &kit.components {
+        post_movement(
+            &api,
+            component.product_id,
+            location_id,
+            -Quantity::from(component.amount.decimal() * quantity.decimal()),
+            AdjustmentReason::Assembly,
+            &note,
+        )
+        .await
+        .map_err(err_to_string)?;
+    }
+    roll_up_cost(&api, &mut kit, &note)
+        .await
+        .map_err(err_to_string)?;
+    post_movement(
+        &api,
+        kit_id,
+        location_id,
+        quantity,
+        AdjustmentReason::Assembly,
+        &note,
+    )
+    .await
+    .map_err(err_to_string)?;
+    let kit = api.get_product(kit_id).await.map_err(err_to_string)?;
+    availability(&api, kit, location_id)
+        .await
+        .map_err(err_to_string)
+}
+
+/// Breaks `quantity` kits back up into their components.
+#[tauri::command]
+pub async fn disassemble_kit(
+    state: tauri::State<'_, AppState>,
+    kit_id: i32,
+    quantity: Quantity,
+    location_id: Option<i32>,
+) -> Result<KitAvailability, String> {
+    check_kit_quantity(&quantity).map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    let kit = get_kit(&api, kit_id).await.map_err(err_to_string)?;
+    let on_hand = available(&api, &kit, location_id)
+        .await
+        .map_err(err_to_string)?;
+    if on_hand < quantity {
+        return Err(format!("Only {} of {} are assembled", on_hand, kit.name));
+    }
+    let note = format!("Disassembled {} x {}", quantity, kit.name);
+    post_movement(
+        &api,
+        kit_id,
+        location_id,
+        -&quantity,
+        AdjustmentReason::Disassembly,
+        &note,
+    )
+    .await
+    .map_err(err_to_string)?;
+    for component in &kit.components {
+        post_movement(
+            &api,
+            component.product_id,
+            location_id,
+            Quantity::from(component.amount.decimal() * quantity.decimal()),
+            AdjustmentReason::Disassembly,
+            &note,
+        )
+        .await
+        .map_err(err_to_string)?;
+    }
+    let kit = api.get_product(kit_id).await.map_err(err_to_string)?;
+    availability(&api, kit, location_id)
+        .await
+        .map_err(err_to_string)
+}
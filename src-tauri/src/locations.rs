@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::client::Api;
+use crate::models::{ArchivedEntity, Location, LocationKind, LocationStock};
+use crate::quantity::Quantity;
+use crate::recycle_bin;
+use crate::{audit, err_to_string, AppState};
+
+/// Stock of a product that isn't at any location, such as what it had before locations were
+/// set up or what was received without one.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UnassignedStock {
+    product_id: i32,
+    name: String,
+    amount: Quantity,
+}
+
+/// The location itself followed by every bin or shelf nested under it.
+pub fn descendants(locations: &[Location], id: i32) -> Vec<i32> {
+    let mut found = vec![id];
+    let mut index = 0;
+    while index < found.len() {
+        let parent = found[index];
+        found.extend(
+            locations
+                .iter()
+                .filter(|location| location.parent_id == Some(parent))
+                .map(|location| location.id),
+        );
+        index += 1;
+    }
+    found
+}
+
+/// Stock of every product held at a location, including its bins, keyed by product id.
+/// Buy levels are the ones set on the location itself.
+pub async fn stock_within(
+    api: &Api,
+    location_id: i32,
+) -> Result<HashMap<i32, LocationStock>, Error> {
+    let locations = api.get_locations().await?;
+    let mut stock: HashMap<i32, LocationStock> = HashMap::new();
+    for id in descendants(&locations, location_id) {
+        for entry in api.get_location_stock(id).await? {
+            let total = stock.entry(entry.product_id).or_insert(LocationStock {
+                location_id,
+                product_id: entry.product_id,
+                amount: Quantity::zero(),
+                buy_level: None,
+            });
+            total.amount += entry.amount;
+            if id == location_id {
+                total.buy_level = entry.buy_level;
+            }
+        }
+    }
+    Ok(stock)
+}
+
+fn validate_location(locations: &[Location], location: &Location) -> Result<(), Error> {
+    match location.parent_id {
+        None if location.kind == LocationKind::Bin => {
+            Err(anyhow!("A bin has to be inside another location"))
+        }
+        None => Ok(()),
+        Some(parent_id) => {
+            if !locations.iter().any(|location| location.id == parent_id) {
+                return Err(anyhow!("Parent location doesn't exist"));
+            }
+            if descendants(locations, location.id).contains(&parent_id) {
+                return Err(anyhow!("A location can't be inside itself"));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_locations(state: tauri::State<'_, AppState>) -> Result<Vec<Location>, String> {
+    state
+        .0
+        .lock()
+        .await
+        .get_locations()
+        .await
+        .map_err(err_to_string)
+}
+
+#[tauri::command]
+pub async fn new_location(
+    state: tauri::State<'_, AppState>,
+    name: String,
+    kind: LocationKind,
+    parent_id: Option<i32>,
+) -> Result<Location, String> {
+    let api = state.0.lock().await;
+    let mut location = Location {
+        id: 0,
+        name,
+        kind,
+        parent_id,
+    };
+    validate_location(
+        &api.get_locations().await.map_err(err_to_string)?,
+        &location,
+    )
+    .map_err(err_to_string)?;
+    location.id = api
+        .new_location(&location.name, kind, parent_id)
+        .await
+        .map_err(err_to_string)?;
+    audit::created(&api, "location", location.id, &location)
+        .await
+        .map_err(err_to_string)?;
+    Ok(location)
+}
+
+#[tauri::command]
+pub async fn save_location(
+    state: tauri::State<'_, AppState>,
+    location: Location,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let locations = api.get_locations().await.map_err(err_to_string)?;
+    validate_location(&locations, &location).map_err(err_to_string)?;
+    let before = locations
+        .into_iter()
+        .find(|before| before.id == location.id)
+        .ok_or_else(|| format!("Location #{} doesn't exist", location.id))?;
+    api.update_location(&location)
+        .await
+        .map_err(err_to_string)?;
+    audit::updated(&api, "location", location.id, &before, &location)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_location(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let locations = api.get_locations().await.map_err(err_to_string)?;
+    let location = locations
+        .iter()
+        .find(|location| location.id == id)
+        .ok_or_else(|| format!("Location #{} doesn't exist", id))?;
+    if locations
+        .iter()
+        .any(|location| location.parent_id == Some(id))
+    {
+        return Err(String::from("Location still has bins inside it"));
+    }
+    let stock = api.get_location_stock(id).await.map_err(err_to_string)?;
+    if stock.iter().any(|stock| !stock.amount.is_zero()) {
+        return Err(String::from("Location still has stock"));
+    }
+    api.remove_location(id).await.map_err(err_to_string)?;
+    audit::deleted(&api, "location", id, location)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn product_location_stock(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+) -> Result<Vec<LocationStock>, String> {
+    state
+        .0
+        .lock()
+        .await
+        .get_product_location_stock(product_id)
+        .await
+        .map_err(err_to_string)
+}
+
+/// Products whose stock at locations doesn't add up to their amount, and by how much.
+#[tauri::command]
+pub async fn unassigned_stock(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<UnassignedStock>, String> {
+    let api = state.0.lock().await;
+    let mut located: HashMap<i32, Quantity> = HashMap::new();
+    for location in api.get_locations().await.map_err(err_to_string)? {
+        for stock in api
+            .get_location_stock(location.id)
+            .await
+            .map_err(err_to_string)?
+        {
+            *located.entry(stock.product_id).or_default() += stock.amount;
+        }
+    }
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(api
+        .all_products()
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .filter(|product| !archived.contains(ArchivedEntity::Product, product.id))
+        .filter_map(|product| {
+            let amount = &product.amount - &located.remove(&product.id).unwrap_or_default();
+            if amount.is_zero() {
+                return None;
+            }
+            Some(UnassignedStock {
+                product_id: product.id,
+                name: product.name,
+                amount,
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn set_location_buy_level(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+    location_id: i32,
+    buy_level: Option<Quantity>,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let mut stock = api
+        .get_product_location_stock(product_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .find(|stock| stock.location_id == location_id)
+        .unwrap_or(LocationStock {
+            location_id,
+            product_id,
+            amount: Quantity::zero(),
+            buy_level: None,
+        });
+    stock.buy_level = buy_level;
+    api.update_location_stock(&stock)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Context, Error};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::client::Api;
+use crate::models::{Lot, Product, Unit};
+use crate::quantity::Quantity;
+use crate::stock::value_at_cost;
+use crate::units::to_base;
+use crate::{err_to_string, AppState};
+
+/// One lot of a received order as entered on the receiving screen.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppLotReceipt {
+    lot_number: String,
+    best_before: String,
+    amount: Quantity,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppLot {
+    id: i32,
+    product_id: i32,
+    received_order_id: Option<i32>,
+    lot_number: String,
+    best_before: String,
+    received: String,
+    amount: Quantity,
+}
+
+impl AppLotReceipt {
+    /// The same lot with its amount in the product's base unit.
+    pub fn into_base(self, product: &Product, unit: Unit) -> Result<Self, Error> {
+        Ok(AppLotReceipt {
+            amount: to_base(product, &self.amount, unit)?,
+            ..self
+        })
+    }
+}
+
+impl AppLot {
+    fn from_lot(lot: Lot) -> Self {
+        AppLot {
+            id: lot.id,
+            product_id: lot.product_id,
+            received_order_id: lot.received_order_id,
+            lot_number: lot.lot_number,
+            best_before: format_date(lot.best_before),
+            received: lot.received.format("%m/%d/%Y").to_string(),
+            amount: lot.amount,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ExpiringLot {
+    lot_id: i32,
+    product_id: i32,
+    name: String,
+    lot_number: String,
+    best_before: String,
+    amount: Quantity,
+    value_at_cost: String,
+}
+
+fn format_date(date: Option<NaiveDate>) -> String {
+    date.map(|date| date.format("%m/%d/%Y").to_string())
+        .unwrap_or_default()
+}
+
+/// Orders lots first-expired-first-out. Lots without a best-before date go last, and lots
+/// expiring on the same day are used in the order they came in.
+pub fn fefo_order(lots: &mut [Lot]) {
+    lots.sort_by_key(|lot| (lot.best_before.is_none(), lot.best_before, lot.received));
+}
+
+/// Turns the lots entered for a received order into lots of the product. The lot amounts
+/// have to add up to what was actually received.
+pub fn lots_from_receipts(
+    product_id: i32,
+    received: NaiveDateTime,
+    actually_received: &Quantity,
+    receipts: Vec<AppLotReceipt>,
+) -> Result<Vec<Lot>, Error> {
+    let total: Quantity = receipts.iter().map(|receipt| &receipt.amount).sum();
+    if total != *actually_received {
+        return Err(anyhow!(
+            "Lots add up to {} but {} were received",
+            total,
+            actually_received
+        ));
+    }
+    receipts
+        .into_iter()
+        .map(|receipt| {
+            if receipt.lot_number.trim().is_empty() {
+                return Err(anyhow!("Every lot needs a lot number"));
+            }
+            let best_before = match receipt.best_before.as_str() {
+                "" => None,
+                date => Some(
+                    NaiveDate::parse_from_str(date, "%m/%d/%Y")
+                        .context("Can't convert best before date")?,
+                ),
+            };
+            Ok(Lot {
+                id: 0,
+                product_id,
+                received_order_id: None,
+                lot_number: receipt.lot_number,
+                best_before,
+                received,
+                amount: receipt.amount,
+            })
+        })
+        .collect()
+}
+
+/// Takes stock out of a product's lots first-expired-first-out. Stock that isn't in any
+/// lot, such as stock from before lots were tracked, is left alone.
+pub async fn consume_fefo(api: &Api, product_id: i32, amount: &Quantity) -> Result<(), Error> {
+    let mut lots = api
+        .get_product_lots(product_id)
+        .await?
+        .into_iter()
+        .filter(|lot| lot.amount.is_positive())
+        .collect::<Vec<_>>();
+    fefo_order(&mut lots);
+    let mut remaining = amount.clone();
+    for mut lot in lots {
+        if !remaining.is_positive() {
+            break;
+        }
+        let taken = remaining.clone().min(lot.amount.clone());
+        lot.amount -= &taken;
+        remaining -= taken;
+        api.update_lot(&lot).await?;
+    }
+    Ok(())
+}
+
+/// A product's lots in the order they should be used.
+#[tauri::command]
+pub async fn product_lots(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+) -> Result<Vec<AppLot>, String> {
+    let mut lots = state
+        .0
+        .lock()
+        .await
+        .get_product_lots(product_id)
+        .await
+        .map_err(err_to_string)?;
+    fefo_order(&mut lots);
+    Ok(lots.into_iter().map(AppLot::from_lot).collect())
+}
+
+/// Lots still in stock that expire within the given number of days, including ones that
+/// have already expired.
+#[tauri::command]
+pub async fn expiring_soon(
+    state: tauri::State<'_, AppState>,
+    days: i64,
+) -> Result<Vec<ExpiringLot>, String> {
+    let api = state.0.lock().await;
+    let cutoff = Utc::now().date_naive() + Duration::days(days);
+    let mut lots = api
+        .all_lots()
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .filter(|lot| {
+            lot.amount.is_positive() && lot.best_before.map_or(false, |date| date <= cutoff)
+        })
+        .collect::<Vec<_>>();
+    fefo_order(&mut lots);
+
+    let mut products = HashMap::new();
+    let mut expiring = Vec::new();
+    for lot in lots {
+        let product = match products.entry(lot.product_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                api.get_product(lot.product_id)
+                    .await
+                    .map_err(err_to_string)?,
+            ),
+        };
+        expiring.push(ExpiringLot {
+            lot_id: lot.id,
+            product_id: lot.product_id,
+            name: product.name.clone(),
+            lot_number: lot.lot_number,
+            best_before: format_date(lot.best_before),
+            value_at_cost: value_at_cost(&lot.amount, &product.cost_price_per_unit).to_string(),
+            amount: lot.amount,
+        });
+    }
+    Ok(expiring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lot(id: i32, best_before: Option<(u32, u32)>, received_day: u32) -> Lot {
+        Lot {
+            id,
+            product_id: 1,
+            received_order_id: None,
+            lot_number: format!("L{}", id),
+            best_before: best_before
+                .map(|(month, day)| NaiveDate::from_ymd_opt(2024, month, day).unwrap()),
+            received: NaiveDate::from_ymd_opt(2024, 1, received_day)
+                .unwrap()
+                .and_hms_opt(8, 0, 0)
+                .unwrap(),
+            amount: Quantity::from(5),
+        }
+    }
+
+    #[test]
+    fn uses_the_first_to_expire_first() {
+        let mut lots = vec![
+            lot(1, None, 1),
+            lot(2, Some((6, 1)), 2),
+            lot(3, Some((3, 15)), 5),
+            lot(4, Some((6, 1)), 1),
+            lot(5, None, 3),
+        ];
+        fefo_order(&mut lots);
+        let order = lots.iter().map(|lot| lot.id).collect::<Vec<_>>();
+        assert_eq!(order, [3, 4, 2, 1, 5]);
+    }
+}
@@ -3,8 +3,33 @@
     windows_subsystem = "windows"
 )]
 
+mod audit;
+mod backup;
+mod bulk_edit;
+mod catalog;
 mod client;
+mod counting;
+mod dependents;
+mod export;
+mod history;
+mod kits;
+mod locations;
+mod lots;
 mod models;
+mod pos_import;
+mod pricing;
+mod product_import;
+mod quantity;
+mod recycle_bin;
+mod relations;
+mod returns;
+mod sales;
+mod scorecard;
+mod serials;
+mod stock;
+mod transfers;
+mod units;
+mod variants;
 
 use anyhow::{Context, Error};
 use asciimath::{eval, scope, Scope};
@@ -12,8 +37,14 @@ use bigdecimal::{BigDecimal, Zero};
 use chrono::{NaiveDate, NaiveDateTime};
 use client::Api;
 use futures::lock::Mutex;
-use models::{Brand, Category, PendingOrder, Product, ReceivedOrder, Supplier};
+use history::{Change, Record};
+use models::{
+    AdjustmentReason, ArchivedEntity, Brand, Category, KitComponent, PendingOrder, Product,
+    ReceivedOrder, Supplier, Unit, VariantOption,
+};
 use ordered_float::NotNan;
+use quantity::Quantity;
+use relations::{ProductRelations, Relations};
 use rust_fuzzy_search::fuzzy_search_sorted;
 use serde::{Deserialize, Serialize};
 use std::{str::FromStr, sync::Arc};
@@ -38,12 +69,31 @@ struct AppProduct {
     name: String,
     upc: String,
     description: String,
-    buyLevel: Option<f64>,
+    buyLevel: Option<Quantity>,
     costPrice: String,
     sellingPrice: String,
-    amount: f64,
+    amount: Quantity,
     case_size: Option<i32>,
     measureByWeight: bool,
+    #[serde(default)]
+    trackSerials: bool,
+    #[serde(default)]
+    baseUnit: Unit,
+    #[serde(default)]
+    purchaseUnit: Unit,
+    #[serde(default)]
+    saleUnit: Unit,
+    #[serde(default)]
+    parentId: Option<i32>,
+    #[serde(default)]
+    variantAttributes: Vec<String>,
+    #[serde(default)]
+    variantOptions: Vec<VariantOption>,
+    #[serde(default)]
+    components: Vec<KitComponent>,
+    /// Left out when saving to keep the brand, categories and suppliers as they are.
+    #[serde(default)]
+    relations: Option<ProductRelations>,
 }
 
 #[derive(Clone, Deserialize, Serialize, Default)]
@@ -62,21 +112,61 @@ struct AppSupplier {
     products: Vec<i32>,
 }
 
+/// Where a received order went and how it was counted. Quantities are in `unit`, the
+/// product's base unit if none is given.
+#[derive(Clone, Deserialize, Serialize, Default)]
+struct AppReceipt {
+    #[serde(default)]
+    location_id: Option<i32>,
+    #[serde(default)]
+    lots: Option<Vec<lots::AppLotReceipt>>,
+    #[serde(default)]
+    serials: Vec<String>,
+    #[serde(default)]
+    unit: Option<Unit>,
+}
+
 #[derive(Clone, Deserialize, Serialize, Default)]
 struct AppPendingOrder {
     id: i32,
     product: i32,
-    amount: f64,
+    amount: Quantity,
+    #[serde(default)]
+    supplier_id: Option<i32>,
+    #[serde(default)]
+    cost_price: String,
+    #[serde(default)]
+    ordered: String,
+    #[serde(default)]
+    expected: String,
+}
+
+fn format_optional_date(date: Option<NaiveDate>) -> String {
+    date.map(|date| date.format("%m/%d/%Y").to_string())
+        .unwrap_or_default()
+}
+
+fn parse_optional_date(date: &str) -> Result<Option<NaiveDate>, Error> {
+    match date {
+        "" => Ok(None),
+        date => Ok(Some(NaiveDate::parse_from_str(date, "%m/%d/%Y")?)),
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize, Default)]
 struct AppReceivedOrder {
     id: i32,
     product_id: i32,
-    gross_amount: f64,
-    actually_received: f64,
-    damaged: f64,
+    gross_amount: Quantity,
+    actually_received: Quantity,
+    damaged: Quantity,
     received: String,
+    #[serde(default)]
+    supplier_id: Option<i32>,
+    #[serde(default)]
+    ordered: String,
+    #[serde(default)]
+    expected: String,
 }
 
 impl AppReceivedOrder {
@@ -93,20 +183,26 @@ impl AppReceivedOrder {
                 .date()
                 .format("%m/%d/%Y")
                 .to_string(),
+            supplier_id: order.supplier_id,
+            ordered: format_optional_date(order.ordered),
+            expected: format_optional_date(order.expected),
         }
     }
     fn to_order(&self) -> Result<ReceivedOrder, Error> {
         Ok(ReceivedOrder {
             id: self.id,
             product_id: self.product_id,
-            actually_received: self.actually_received,
-            damaged: self.damaged,
+            actually_received: self.actually_received.clone(),
+            damaged: self.damaged.clone(),
             received: Some(
                 NaiveDate::parse_from_str(&self.received, "%m/%d/%Y")?
                     .and_hms_opt(0, 0, 0)
                     .unwrap(),
             ),
-            gross_amount: self.gross_amount,
+            gross_amount: self.gross_amount.clone(),
+            supplier_id: self.supplier_id,
+            ordered: parse_optional_date(&self.ordered)?,
+            expected: parse_optional_date(&self.expected)?,
         })
     }
 }
@@ -117,13 +213,27 @@ impl AppPendingOrder {
             id: order.id,
             product: order.product_id,
             amount: order.amount,
+            supplier_id: order.supplier_id,
+            cost_price: order
+                .cost_per_unit
+                .map(|cost| cost.to_string())
+                .unwrap_or_default(),
+            ordered: format_optional_date(order.ordered),
+            expected: format_optional_date(order.expected),
         }
     }
     fn to_order(&self) -> Result<PendingOrder, Error> {
         Ok(PendingOrder {
             id: self.id,
             product_id: self.product,
-            amount: self.amount,
+            amount: self.amount.clone(),
+            supplier_id: self.supplier_id,
+            cost_per_unit: match self.cost_price.as_str() {
+                "" => None,
+                cost => Some(BigDecimal::from_str(cost)?),
+            },
+            ordered: parse_optional_date(&self.ordered)?,
+            expected: parse_optional_date(&self.expected)?,
         })
     }
 }
@@ -237,22 +347,43 @@ impl AppProduct {
             amount: product.amount,
             case_size: product.case_size,
             measureByWeight: product.measure_by_weight,
+            trackSerials: product.track_serials,
+            baseUnit: product.base_unit,
+            purchaseUnit: product.purchase_unit,
+            saleUnit: product.sale_unit,
+            parentId: product.parent_id,
+            variantAttributes: product.variant_attributes,
+            variantOptions: product.variant_options,
+            components: product.components,
+            relations: None,
         }
     }
+    fn with_relations(mut self, relations: &Relations) -> Self {
+        self.relations = Some(relations.of(self.id));
+        self
+    }
     fn to_product(&self) -> Result<Product, Error> {
         Ok(Product {
             id: self.id,
             upc: self.upc.clone(),
             name: self.name.clone(),
             description: self.description.clone(),
-            buy_level: self.buyLevel,
-            amount: self.amount,
+            buy_level: self.buyLevel.clone(),
+            amount: self.amount.clone(),
             case_size: self.case_size,
             sale_end: None,
             sale_price: None,
             measure_by_weight: self.measureByWeight,
             cost_price_per_unit: BigDecimal::from_str(&self.costPrice)?,
             selling_price_per_unit: BigDecimal::from_str(&self.sellingPrice)?,
+            track_serials: self.trackSerials,
+            base_unit: self.baseUnit,
+            purchase_unit: self.purchaseUnit,
+            sale_unit: self.saleUnit,
+            parent_id: self.parentId,
+            variant_attributes: self.variantAttributes.clone(),
+            variant_options: self.variantOptions.clone(),
+            components: self.components.clone(),
         })
     }
 }
@@ -288,14 +419,14 @@ struct CategoryName {
 
 #[tauri::command]
 async fn category_names(state: tauri::State<'_, AppState>) -> Result<Vec<CategoryName>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(api
         .category_names()
         .await
         .map_err(err_to_string)?
         .into_iter()
+        .filter(|(_, id)| !archived.contains(ArchivedEntity::Category, *id))
         .map(|(name, id)| CategoryName { name, id })
         .collect())
 }
@@ -317,14 +448,14 @@ async fn sort_categories(
 
 #[tauri::command]
 async fn supplier_names(state: tauri::State<'_, AppState>) -> Result<Vec<SupplierName>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(api
         .supplier_names()
         .await
         .map_err(err_to_string)?
         .into_iter()
+        .filter(|(_, id)| !archived.contains(ArchivedEntity::Supplier, *id))
         .map(|(name, id)| SupplierName { name, id })
         .collect())
 }
@@ -361,28 +492,44 @@ async fn sort_suppliers(
 
 #[tauri::command]
 async fn brand_names(state: tauri::State<'_, AppState>) -> Result<Vec<BrandName>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(api
         .brand_names()
         .await
         .map_err(err_to_string)?
         .into_iter()
+        .filter(|(_, id)| !archived.contains(ArchivedEntity::Brand, *id))
         .map(|(name, id)| BrandName { name, id })
         .collect())
 }
 
 #[tauri::command]
-async fn product_names(state: tauri::State<'_, AppState>) -> Result<Vec<ProductName>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
+async fn product_names(
+    state: tauri::State<'_, AppState>,
+    location_id: Option<i32>,
+) -> Result<Vec<ProductName>, String> {
+    let api = state.0.lock().await;
+    let stocked = match location_id {
+        Some(location_id) => Some(
+            locations::stock_within(&api, location_id)
+                .await
+                .map_err(err_to_string)?,
+        ),
+        None => None,
+    };
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(api
         .product_names()
         .await
         .map_err(err_to_string)?
         .into_iter()
+        .filter(|(_, _, id)| {
+            !archived.contains(ArchivedEntity::Product, *id)
+                && stocked
+                    .as_ref()
+                    .map_or(true, |stock| stock.contains_key(id))
+        })
         .map(|(name, upc, id)| ProductName { name, upc, id })
         .collect())
 }
@@ -424,11 +571,17 @@ struct AppState(Arc<Mutex<Api>>);
 #[tauri::command]
 async fn log_in(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     username: &str,
     password: &str,
+    base_url: Option<String>,
 ) -> Result<(), String> {
     // I don't want to set the mutex contents on an error here
-    let api = Api::new(username, password).await.map_err(err_to_string)?;
+    let api = match base_url {
+        Some(base_url) => Api::connect(&base_url, username, password).await,
+        None => Api::new(username, password).await,
+    }
+    .map_err(err_to_string)?;
 
     // Introduce an extra scope to force the mutex guard to release when the scope
     // closes.
@@ -437,6 +590,7 @@ async fn log_in(
         *client = api;
         client.permissions().await.map_err(err_to_string)?
     };
+    history::clear(&history).await;
 
     Ok(())
 }
@@ -446,88 +600,219 @@ async fn get_products(
     state: tauri::State<'_, AppState>,
     limit: i64,
     offset: i64,
+    location_id: Option<i32>,
 ) -> Result<Vec<AppProduct>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
-        .get_products(limit, offset)
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    let relations = Relations::load(&api).await.map_err(err_to_string)?;
+    let location_id = match location_id {
+        Some(location_id) => location_id,
+        None => {
+            return Ok(recycle_bin::visible_page(
+                limit,
+                offset,
+                |product: &Product| archived.contains(ArchivedEntity::Product, product.id),
+                |limit, offset| api.get_products(limit, offset),
+            )
+            .await
+            .map_err(err_to_string)?
+            .into_iter()
+            .map(|product| AppProduct::from_product(product).with_relations(&relations))
+            .collect())
+        }
+    };
+
+    // Only products stocked at the location, with the location's amount and buy level.
+    let mut stocked = locations::stock_within(&api, location_id)
         .await
         .map_err(err_to_string)?
+        .into_values()
+        .filter(|stock| !archived.contains(ArchivedEntity::Product, stock.product_id))
+        .collect::<Vec<_>>();
+    stocked.sort_by_key(|stock| stock.product_id);
+    let mut products = Vec::new();
+    for stock in stocked
         .into_iter()
-        .map(AppProduct::from_product)
-        .collect())
+        .skip(offset as usize)
+        .take(limit as usize)
+    {
+        let mut product = AppProduct::from_product(
+            api.get_product(stock.product_id)
+                .await
+                .map_err(err_to_string)?,
+        )
+        .with_relations(&relations);
+        product.amount = stock.amount;
+        product.buyLevel = stock.buy_level;
+        products.push(product);
+    }
+    Ok(products)
 }
 
 #[tauri::command]
-async fn remove_received_order(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
-    state
-        .0
-        .lock()
-        .await
-        .remove_received_order(id)
+async fn remove_received_order(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    id: i32,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let received_order = api.get_received_order(id).await.map_err(err_to_string)?;
+    recycle_bin::archive(
+        &api,
+        ArchivedEntity::ReceivedOrder,
+        id,
+        &format!("Received order #{}", id),
+    )
+    .await
+    .map_err(err_to_string)?;
+    audit::deleted(&api, "received_order", id, &received_order)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Deleted(Record::ReceivedOrder(received_order)),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
-async fn remove_category(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
-    state
-        .0
-        .lock()
+async fn remove_category(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    id: i32,
+    resolution: Option<dependents::Resolution>,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let mut changes = dependents::resolve(
+        &api,
+        ArchivedEntity::Category,
+        id,
+        resolution.unwrap_or_default(),
+    )
+    .await
+    .map_err(err_to_string)?;
+    let category = api.get_category(id).await.map_err(err_to_string)?;
+    recycle_bin::archive(&api, ArchivedEntity::Category, id, &category.name)
         .await
-        .remove_category(id)
+        .map_err(err_to_string)?;
+    audit::deleted(&api, "category", id, &category)
         .await
         .map_err(err_to_string)?;
+    changes.push(Change::Deleted(Record::Category(category)));
+    history::record_all(&history, changes).await;
     Ok(())
 }
 
 #[tauri::command]
-async fn remove_product(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
-    state
-        .0
-        .lock()
+async fn remove_product(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    id: i32,
+    resolution: Option<dependents::Resolution>,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let product = api.get_product(id).await.map_err(err_to_string)?;
+    let links = Relations::load(&api).await.map_err(err_to_string)?.of(id);
+    let mut changes = dependents::resolve(
+        &api,
+        ArchivedEntity::Product,
+        id,
+        resolution.unwrap_or_default(),
+    )
+    .await
+    .map_err(err_to_string)?;
+    recycle_bin::archive(&api, ArchivedEntity::Product, id, &product.name)
         .await
-        .remove_product(id)
+        .map_err(err_to_string)?;
+    audit::deleted(&api, "product", id, &product)
         .await
         .map_err(err_to_string)?;
+    changes.push(Change::Deleted(Record::Product(Box::new(product), links)));
+    history::record_all(&history, changes).await;
     Ok(())
 }
 
 #[tauri::command]
-async fn remove_pending_order(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
-    state
-        .0
-        .lock()
-        .await
-        .remove_pending_order(id)
+async fn remove_pending_order(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    id: i32,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let pending_order = api.get_pending_order(id).await.map_err(err_to_string)?;
+    recycle_bin::archive(
+        &api,
+        ArchivedEntity::PendingOrder,
+        id,
+        &format!("Pending order #{}", id),
+    )
+    .await
+    .map_err(err_to_string)?;
+    audit::deleted(&api, "pending_order", id, &pending_order)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Deleted(Record::PendingOrder(pending_order)),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
-async fn remove_brand(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
-    state
-        .0
-        .lock()
+async fn remove_brand(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    id: i32,
+    resolution: Option<dependents::Resolution>,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let mut changes = dependents::resolve(
+        &api,
+        ArchivedEntity::Brand,
+        id,
+        resolution.unwrap_or_default(),
+    )
+    .await
+    .map_err(err_to_string)?;
+    let brand = api.get_brand(id).await.map_err(err_to_string)?;
+    recycle_bin::archive(&api, ArchivedEntity::Brand, id, &brand.name)
         .await
-        .remove_brand(id)
+        .map_err(err_to_string)?;
+    audit::deleted(&api, "brand", id, &brand)
         .await
         .map_err(err_to_string)?;
+    changes.push(Change::Deleted(Record::Brand(brand)));
+    history::record_all(&history, changes).await;
     Ok(())
 }
 
 #[tauri::command]
-async fn remove_supplier(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
-    state
-        .0
-        .lock()
+async fn remove_supplier(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    id: i32,
+    resolution: Option<dependents::Resolution>,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let mut changes = dependents::resolve(
+        &api,
+        ArchivedEntity::Supplier,
+        id,
+        resolution.unwrap_or_default(),
+    )
+    .await
+    .map_err(err_to_string)?;
+    let supplier = api.get_supplier(id).await.map_err(err_to_string)?;
+    recycle_bin::archive(&api, ArchivedEntity::Supplier, id, &supplier.name)
         .await
-        .remove_supplier(id)
+        .map_err(err_to_string)?;
+    audit::deleted(&api, "supplier", id, &supplier)
         .await
         .map_err(err_to_string)?;
+    changes.push(Change::Deleted(Record::Supplier(supplier)));
+    history::record_all(&history, changes).await;
     Ok(())
 }
 
@@ -537,16 +822,19 @@ async fn get_brands(
     limit: i64,
     offset: i64,
 ) -> Result<Vec<AppBrand>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
-        .get_brands(limit, offset)
-        .await
-        .map_err(err_to_string)?
-        .into_iter()
-        .map(AppBrand::from_brand)
-        .collect())
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(recycle_bin::visible_page(
+        limit,
+        offset,
+        |record: &Brand| archived.contains(ArchivedEntity::Brand, record.id),
+        |limit, offset| api.get_brands(limit, offset),
+    )
+    .await
+    .map_err(err_to_string)?
+    .into_iter()
+    .map(AppBrand::from_brand)
+    .collect())
 }
 
 #[tauri::command]
@@ -555,13 +843,16 @@ async fn get_categories(
     limit: i64,
     offset: i64,
 ) -> Result<Vec<Category>, String> {
-    state
-        .0
-        .lock()
-        .await
-        .get_categories(limit, offset)
-        .await
-        .map_err(err_to_string)
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    recycle_bin::visible_page(
+        limit,
+        offset,
+        |record: &Category| archived.contains(ArchivedEntity::Category, record.id),
+        |limit, offset| api.get_categories(limit, offset),
+    )
+    .await
+    .map_err(err_to_string)
 }
 
 #[tauri::command]
@@ -570,16 +861,19 @@ async fn get_received_orders(
     limit: i64,
     offset: i64,
 ) -> Result<Vec<AppReceivedOrder>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
-        .get_received_orders(limit, offset)
-        .await
-        .map_err(err_to_string)?
-        .into_iter()
-        .map(|order| AppReceivedOrder::from_order(order))
-        .collect())
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(recycle_bin::visible_page(
+        limit,
+        offset,
+        |record: &ReceivedOrder| archived.contains(ArchivedEntity::ReceivedOrder, record.id),
+        |limit, offset| api.get_received_orders(limit, offset),
+    )
+    .await
+    .map_err(err_to_string)?
+    .into_iter()
+    .map(|order| AppReceivedOrder::from_order(order))
+    .collect())
 }
 
 #[tauri::command]
@@ -588,16 +882,19 @@ async fn get_pending_orders(
     limit: i64,
     offset: i64,
 ) -> Result<Vec<AppPendingOrder>, String> {
-    Ok(state
-        .0
-        .lock()
-        .await
-        .get_pending_orders(limit, offset)
-        .await
-        .map_err(err_to_string)?
-        .into_iter()
-        .map(|order| AppPendingOrder::from_order(order))
-        .collect())
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    Ok(recycle_bin::visible_page(
+        limit,
+        offset,
+        |record: &PendingOrder| archived.contains(ArchivedEntity::PendingOrder, record.id),
+        |limit, offset| api.get_pending_orders(limit, offset),
+    )
+    .await
+    .map_err(err_to_string)?
+    .into_iter()
+    .map(|order| AppPendingOrder::from_order(order))
+    .collect())
 }
 
 #[tauri::command]
@@ -605,17 +902,45 @@ async fn mark_order_received(
     state: tauri::State<'_, AppState>,
     order: AppPendingOrder,
     date: String,
-    actually_received: f64,
-    damaged: f64,
+    actually_received: Quantity,
+    damaged: Quantity,
+    receipt: Option<AppReceipt>,
 ) -> Result<AppReceivedOrder, String> {
+    let AppReceipt {
+        location_id,
+        lots,
+        serials,
+        unit,
+    } = receipt.unwrap_or_default();
+    let api = state.0.lock().await;
+    let product = api
+        .get_product(order.product)
+        .await
+        .map_err(err_to_string)?;
+    // Quantities come in whatever unit the order was counted in but are stored in the base unit.
+    let unit = unit.unwrap_or(product.base_unit);
+    let actually_received =
+        units::to_base(&product, &actually_received, unit).map_err(err_to_string)?;
+    let damaged = units::to_base(&product, &damaged, unit).map_err(err_to_string)?;
+    let lots = lots
+        .map(|lots| {
+            lots.into_iter()
+                .map(|lot| lot.into_base(&product, unit))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map_err(err_to_string)?;
     println!("{}", date);
     let mut received = AppReceivedOrder {
         id: order.id,
         product_id: order.product,
         received: date.clone(),
-        damaged,
-        gross_amount: order.amount,
-        actually_received,
+        damaged: damaged.clone(),
+        gross_amount: order.amount.clone(),
+        actually_received: actually_received.clone(),
+        supplier_id: order.supplier_id,
+        ordered: order.ordered.clone(),
+        expected: order.expected.clone(),
     };
     println!("{}", date);
     let date = NaiveDate::parse_from_str(&date, "%m/%d/%Y")
@@ -624,14 +949,76 @@ async fn mark_order_received(
         .and_hms_opt(0, 0, 0)
         .context("Can't convert date to datetime")
         .map_err(err_to_string)?;
-    let id = state
-        .0
-        .lock()
-        .await
-        .mark_as_received(order.id, date, actually_received, damaged)
+    let lots = match lots {
+        Some(lots) => lots::lots_from_receipts(order.product, date, &actually_received, lots)
+            .map_err(err_to_string)?,
+        None => Vec::new(),
+    };
+    if product.track_serials {
+        serials::check_serials(&serials, &actually_received).map_err(err_to_string)?;
+        // Checked before anything is written, so a reused serial doesn't leave the order
+        // half received.
+        serials::check_not_in_stock(&api, order.product, &serials)
+            .await
+            .map_err(err_to_string)?;
+    } else if !serials.is_empty() {
+        return Err(String::from("Product doesn't track serial numbers"));
+    }
+    let id = api
+        .mark_as_received(order.id, date, &actually_received, &damaged)
         .await
         .map_err(err_to_string)?;
     received.id = id;
+    // Keep who the order came from and when it was due for supplier scorecards.
+    let received_order = received.to_order().map_err(err_to_string)?;
+    api.update_received_order(&received_order)
+        .await
+        .map_err(err_to_string)?;
+    audit::created(&api, "received_order", id, &received_order)
+        .await
+        .map_err(err_to_string)?;
+    for mut lot in lots {
+        lot.received_order_id = Some(id);
+        api.new_lot(&lot).await.map_err(err_to_string)?;
+    }
+    if product.track_serials {
+        // Older orders don't say who they came from, so then only a product's sole supplier
+        // is known.
+        let supplier_id = match order.supplier_id {
+            Some(supplier_id) => Some(supplier_id),
+            None => {
+                let suppliers = api
+                    .get_product_suppliers(order.product)
+                    .await
+                    .map_err(err_to_string)?;
+                match suppliers.as_slice() {
+                    [supplier] => Some(supplier.id),
+                    _ => None,
+                }
+            }
+        };
+        serials::stock_serials(
+            &api,
+            order.product,
+            &serials,
+            Some(id),
+            supplier_id,
+            date,
+            &format!("Received order #{}", id),
+        )
+        .await
+        .map_err(err_to_string)?;
+    }
+    stock::post_movement(
+        &api,
+        order.product,
+        location_id,
+        actually_received,
+        AdjustmentReason::Received,
+        &format!("Received order #{}", id),
+    )
+    .await
+    .map_err(err_to_string)?;
     Ok(received)
 }
 
@@ -641,25 +1028,36 @@ async fn get_suppliers(
     limit: i64,
     offset: i64,
 ) -> Result<Vec<Supplier>, String> {
-    state
-        .0
-        .lock()
-        .await
-        .get_suppliers(limit, offset)
-        .await
-        .map_err(err_to_string)
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    recycle_bin::visible_page(
+        limit,
+        offset,
+        |record: &Supplier| archived.contains(ArchivedEntity::Supplier, record.id),
+        |limit, offset| api.get_suppliers(limit, offset),
+    )
+    .await
+    .map_err(err_to_string)
 }
 
 #[tauri::command]
-async fn save_brand(state: tauri::State<'_, AppState>, brand: AppBrand) -> Result<(), String> {
+async fn save_brand(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    brand: AppBrand,
+) -> Result<(), String> {
     let brand = brand.to_brand().map_err(err_to_string)?;
-    state
-        .0
-        .lock()
-        .await
-        .update_brand(&brand)
+    let api = state.0.lock().await;
+    let before = api.get_brand(brand.id).await.map_err(err_to_string)?;
+    api.update_brand(&brand).await.map_err(err_to_string)?;
+    audit::updated(&api, "brand", brand.id, &before, &brand)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Updated(Record::Brand(before), Record::Brand(brand)),
+    )
+    .await;
     Ok(())
 }
 
@@ -682,6 +1080,7 @@ async fn validate_email(
 #[tauri::command]
 async fn save_supplier(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     supplier: AppSupplier,
 ) -> Result<(), String> {
     if (&supplier.phoneNumber == ""
@@ -692,147 +1091,276 @@ async fn save_supplier(
     }
     let supplier = supplier.to_supplier().map_err(err_to_string)?;
     println!("{:?}", supplier);
-    state
-        .0
-        .lock()
+    let api = state.0.lock().await;
+    let before = api.get_supplier(supplier.id).await.map_err(err_to_string)?;
+    api.update_supplier(&supplier)
         .await
-        .update_supplier(&supplier)
+        .map_err(err_to_string)?;
+    audit::updated(&api, "supplier", supplier.id, &before, &supplier)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Updated(Record::Supplier(before), Record::Supplier(supplier)),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
 async fn save_category(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     category: AppCategory,
 ) -> Result<(), String> {
     let category = category.to_category().map_err(err_to_string)?;
-    state
-        .0
-        .lock()
+    let api = state.0.lock().await;
+    let before = api.get_category(category.id).await.map_err(err_to_string)?;
+    api.update_category(&category)
         .await
-        .update_category(&category)
+        .map_err(err_to_string)?;
+    audit::updated(&api, "category", category.id, &before, &category)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Updated(Record::Category(before), Record::Category(category)),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
 async fn save_received_order(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     order: AppReceivedOrder,
 ) -> Result<(), String> {
     println!("{}", order.received);
-    state
-        .0
-        .lock()
+    let order = order.to_order().map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    let before = api
+        .get_received_order(order.id)
         .await
-        .update_received_order(&order.to_order().map_err(err_to_string)?)
+        .map_err(err_to_string)?;
+    api.update_received_order(&order)
+        .await
+        .map_err(err_to_string)?;
+    audit::updated(&api, "received_order", order.id, &before, &order)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Updated(Record::ReceivedOrder(before), Record::ReceivedOrder(order)),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
 async fn save_product(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     product: AppProduct,
+    reason: Option<String>,
 ) -> Result<(), String> {
-    let product = product.to_product().map_err(err_to_string)?;
-    state
-        .0
-        .lock()
+    let links = product.relations.clone();
+    let mut product = product.to_product().map_err(err_to_string)?;
+    units::check_units(&product).map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    let before = api.get_product(product.id).await.map_err(err_to_string)?;
+    let mut relations = Relations::load(&api).await.map_err(err_to_string)?;
+    if let Some(links) = &links {
+        relations.check(links).map_err(err_to_string)?;
+    }
+    let before_links = relations.of(product.id);
+    // Stored quantities are in the base unit, so it can't change once there are any.
+    if product.base_unit != before.base_unit
+        && (!before.amount.is_zero()
+            || !api
+                .get_stock_movements(before.id)
+                .await
+                .map_err(err_to_string)?
+                .is_empty())
+    {
+        return Err(format!(
+            "{} has stock recorded in {}, so its base unit can't change",
+            before.name,
+            before.base_unit.symbol()
+        ));
+    }
+    // Stock only changes through the ledger, so an edited amount becomes a correction.
+    let delta = &product.amount - &before.amount;
+    if !delta.is_zero() && before.track_serials {
+        return Err(String::from(
+            "Stock of serialized products has to be adjusted by serial number",
+        ));
+    }
+    product.amount = before.amount.clone();
+    // Variants and kits are only set up through their own commands.
+    product.parent_id = before.parent_id;
+    product.variant_attributes = before.variant_attributes.clone();
+    product.variant_options = before.variant_options.clone();
+    product.components = before.components.clone();
+    api.update_product(&product).await.map_err(err_to_string)?;
+    if let Some(links) = &links {
+        // The product and its links are saved together, so a failed link puts it back.
+        if let Err(err) = relations.save(&api, product.id, links).await {
+            api.update_product(&before).await.map_err(err_to_string)?;
+            return Err(err_to_string(err));
+        }
+    }
+    if !delta.is_zero() {
+        stock::post_movement(
+            &api,
+            product.id,
+            None,
+            delta.clone(),
+            AdjustmentReason::Correction,
+            "Edited product amount",
+        )
         .await
-        .update_product(&product)
+        .map_err(err_to_string)?;
+    }
+    pricing::record_price_change(&api, &before, &product, &reason.unwrap_or_default())
         .await
         .map_err(err_to_string)?;
+    // The ledger has moved the stock by now, so the entry shows the amount it ended at.
+    product.amount = &before.amount + &delta;
+    audit::updated(&api, "product", product.id, &before, &product)
+        .await
+        .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Updated(
+            Record::Product(Box::new(before), before_links.clone()),
+            Record::Product(Box::new(product), links.unwrap_or(before_links)),
+        ),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
 async fn save_pending_order(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     order: AppPendingOrder,
 ) -> Result<(), String> {
     let order = order.to_order().map_err(err_to_string)?;
-    state
-        .0
-        .lock()
+    let api = state.0.lock().await;
+    let before = api
+        .get_pending_order(order.id)
         .await
-        .update_pending_order(&order)
+        .map_err(err_to_string)?;
+    api.update_pending_order(&order)
+        .await
+        .map_err(err_to_string)?;
+    audit::updated(&api, "pending_order", order.id, &before, &order)
         .await
         .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Updated(Record::PendingOrder(before), Record::PendingOrder(order)),
+    )
+    .await;
     Ok(())
 }
 
 #[tauri::command]
-async fn new_brand(state: tauri::State<'_, AppState>) -> Result<AppBrand, String> {
-    let id = state
-        .0
-        .lock()
-        .await
-        .new_brand("")
+async fn new_brand(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+) -> Result<AppBrand, String> {
+    let api = state.0.lock().await;
+    let id = api.new_brand("").await.map_err(err_to_string)?;
+    let brand = api.get_brand(id).await.map_err(err_to_string)?;
+    audit::created(&api, "brand", id, &brand)
         .await
         .map_err(err_to_string)?;
-    Ok({
-        let mut brand = AppBrand::default();
-        brand.id = id;
-        brand
+    history::record(&history, Change::Created(Record::Brand(brand))).await;
+    Ok(AppBrand {
+        id,
+        ..AppBrand::default()
     })
 }
 
 #[tauri::command]
-async fn new_supplier(state: tauri::State<'_, AppState>) -> Result<AppSupplier, String> {
-    let id = state
-        .0
-        .lock()
-        .await
-        .new_supplier("", "", "")
+async fn new_supplier(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+) -> Result<AppSupplier, String> {
+    let api = state.0.lock().await;
+    let id = api.new_supplier("", "", "").await.map_err(err_to_string)?;
+    let supplier = api.get_supplier(id).await.map_err(err_to_string)?;
+    audit::created(&api, "supplier", id, &supplier)
         .await
         .map_err(err_to_string)?;
-
-    Ok({
-        let mut supplier = AppSupplier::default();
-        supplier.id = id;
-        supplier
+    history::record(&history, Change::Created(Record::Supplier(supplier))).await;
+    Ok(AppSupplier {
+        id,
+        ..AppSupplier::default()
     })
 }
 
 #[tauri::command]
-async fn new_category(state: tauri::State<'_, AppState>) -> Result<AppCategory, String> {
-    let id = state
-        .0
-        .lock()
-        .await
-        .new_category("")
+async fn new_category(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+) -> Result<AppCategory, String> {
+    let api = state.0.lock().await;
+    let id = api.new_category("").await.map_err(err_to_string)?;
+    let category = api.get_category(id).await.map_err(err_to_string)?;
+    audit::created(&api, "category", id, &category)
         .await
         .map_err(err_to_string)?;
-    Ok({
-        let mut category = AppCategory::default();
-        category.id = id;
-        category
+    history::record(&history, Change::Created(Record::Category(category))).await;
+    Ok(AppCategory {
+        id,
+        ..AppCategory::default()
     })
 }
 
 #[tauri::command]
 async fn new_pending_order(
     state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
     product_id: i32,
+    supplier_id: Option<i32>,
+    choice: Option<catalog::SupplierChoice>,
 ) -> Result<AppPendingOrder, String> {
-    let id = state
-        .0
-        .lock()
+    let api = state.0.lock().await;
+    let mut order = catalog::prefill_order(
+        &api,
+        product_id,
+        supplier_id,
+        choice.unwrap_or(catalog::SupplierChoice::Preferred),
+    )
+    .await
+    .map_err(err_to_string)?;
+    order.id = api
+        .new_pending_order(order.amount.clone(), product_id)
         .await
-        .new_pending_order(0.0, product_id)
+        .map_err(err_to_string)?;
+    // The backend only stores the amount and product, so save whatever else was prefilled.
+    let stored = api
+        .get_pending_order(order.id)
         .await
         .map_err(err_to_string)?;
-    Ok({
-        let mut order = AppPendingOrder::default();
-        order.id = id;
-        order.product = product_id;
-        order
-    })
+    if stored != order {
+        api.update_pending_order(&order)
+            .await
+            .map_err(err_to_string)?;
+    }
+    audit::created(&api, "pending_order", order.id, &order)
+        .await
+        .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Created(Record::PendingOrder(order.clone())),
+    )
+    .await;
+    Ok(AppPendingOrder::from_order(order))
 }
 
 #[tauri::command]
@@ -884,12 +1412,38 @@ async fn product_categories(
         .collect())
 }
 
+/// Products with the brand, categories and suppliers they're listed under, in one call.
 #[tauri::command]
-async fn new_product(state: tauri::State<'_, AppState>) -> Result<AppProduct, String> {
-    let id = state
-        .0
-        .lock()
+async fn products_with_relations(
+    state: tauri::State<'_, AppState>,
+    ids: Vec<i32>,
+) -> Result<Vec<AppProduct>, String> {
+    let api = state.0.lock().await;
+    let relations = Relations::load(&api).await.map_err(err_to_string)?;
+    let mut products = Vec::new();
+    for id in ids {
+        products.push(
+            AppProduct::from_product(api.get_product(id).await.map_err(err_to_string)?)
+                .with_relations(&relations),
+        );
+    }
+    Ok(products)
+}
+
+#[tauri::command]
+async fn new_product(
+    state: tauri::State<'_, AppState>,
+    history: tauri::State<'_, history::HistoryState>,
+    relations: Option<ProductRelations>,
+) -> Result<AppProduct, String> {
+    let api = state.0.lock().await;
+    let relations = relations.unwrap_or_default();
+    Relations::load(&api)
         .await
+        .map_err(err_to_string)?
+        .check(&relations)
+        .map_err(err_to_string)?;
+    let id = api
         .new_product(
             "",
             "",
@@ -897,25 +1451,43 @@ async fn new_product(state: tauri::State<'_, AppState>) -> Result<AppProduct, St
             false,
             BigDecimal::zero(),
             BigDecimal::zero(),
-            0.0,
-            Vec::new(),
-            Vec::new(),
-            None,
+            Quantity::zero(),
+            relations.category_ids.clone(),
+            relations.supplier_ids.clone(),
+            relations.brand_id,
         )
         .await
         .map_err(err_to_string)?;
+    let product = api.get_product(id).await.map_err(err_to_string)?;
+    audit::created(&api, "product", id, &product)
+        .await
+        .map_err(err_to_string)?;
+    history::record(
+        &history,
+        Change::Created(Record::Product(Box::new(product), relations.clone())),
+    )
+    .await;
     Ok({
         let product = AppProduct {
             id,
             name: String::from(""),
             upc: String::from(""),
             description: String::from(""),
-            buyLevel: Some(0.0),
+            buyLevel: Some(Quantity::zero()),
             costPrice: String::from(""),
             sellingPrice: String::from(""),
-            amount: 0.0,
+            amount: Quantity::zero(),
             case_size: Some(0),
             measureByWeight: false,
+            trackSerials: false,
+            baseUnit: Unit::Each,
+            purchaseUnit: Unit::Each,
+            saleUnit: Unit::Each,
+            parentId: None,
+            variantAttributes: Vec::new(),
+            variantOptions: Vec::new(),
+            components: Vec::new(),
+            relations: Some(relations),
         };
         product
     })
@@ -927,6 +1499,8 @@ async fn main() {
         .manage(AppState(Arc::new(Mutex::new(
             Api::new("", "").await.unwrap(),
         ))))
+        .manage(pos_import::PosWatchState::default())
+        .manage(history::HistoryState::default())
         .invoke_handler(tauri::generate_handler![
             log_in,
             get_products,
@@ -961,12 +1535,88 @@ async fn main() {
             category_names,
             supplier_names,
             product_categories,
+            products_with_relations,
             product_brand,
             product_suppliers,
             sort_brands,
             sort_suppliers,
             sort_categories,
             brand_names,
+            pos_import::import_pos_file,
+            pos_import::watch_pos_folder,
+            pos_import::stop_pos_watch,
+            audit::audit_log,
+            audit::verify_audit_log,
+            backup::backup,
+            backup::restore,
+            export::export_columns,
+            export::export_entities,
+            history::history_status,
+            history::undo,
+            history::redo,
+            dependents::delete_impact,
+            bulk_edit::preview_bulk_update_products,
+            bulk_edit::bulk_update_products,
+            recycle_bin::recycle_bin,
+            recycle_bin::restore_from_recycle_bin,
+            recycle_bin::purge_from_recycle_bin,
+            recycle_bin::recycle_bin_retention,
+            recycle_bin::set_recycle_bin_retention,
+            pricing::price_history,
+            pricing::preview_price_increase,
+            pricing::apply_price_increase,
+            product_import::product_csv_headers,
+            product_import::import_products_csv,
+            stock::adjust_stock,
+            stock::stock_ledger,
+            catalog::product_supplier_items,
+            catalog::supplier_catalog,
+            catalog::save_supplier_item,
+            catalog::remove_supplier_item,
+            counting::new_count_session,
+            counting::get_count_sessions,
+            counting::set_count,
+            counting::scan_count,
+            counting::count_variances,
+            counting::post_count_session,
+            locations::get_locations,
+            locations::new_location,
+            locations::save_location,
+            locations::remove_location,
+            locations::product_location_stock,
+            locations::unassigned_stock,
+            locations::set_location_buy_level,
+            transfers::new_transfer,
+            transfers::get_transfers,
+            transfers::save_transfer,
+            transfers::remove_transfer,
+            transfers::ship_transfer,
+            transfers::receive_transfer,
+            transfers::in_transit_report,
+            kits::set_kit_components,
+            kits::kit_availability,
+            kits::assemble_kit,
+            kits::disassemble_kit,
+            lots::product_lots,
+            lots::expiring_soon,
+            returns::new_vendor_return,
+            returns::return_damaged,
+            returns::get_vendor_returns,
+            returns::save_vendor_return,
+            returns::remove_vendor_return,
+            returns::ship_vendor_return,
+            returns::credit_vendor_return,
+            sales::record_sale,
+            sales::record_sales,
+            sales::get_sales,
+            sales::product_usage,
+            scorecard::supplier_scorecard,
+            serials::product_serials,
+            serials::serial_lookup,
+            units::convert_quantity,
+            units::quantity_in_units,
+            variants::create_variant_matrix,
+            variants::variant_rollup,
         ])
         .run(tauri::generate_context!())
         .expect("error while running application");
@@ -1,7 +1,9 @@
 use bigdecimal::BigDecimal;
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 
+use crate::quantity::Quantity;
+
 #[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
 pub struct User {
     pub id: i32,
@@ -16,19 +18,73 @@ pub struct Product {
     pub upc: String,
     pub name: String,
     pub description: String,
-    pub amount: f64,
+    pub amount: Quantity,
     pub case_size: Option<i32>,
     pub measure_by_weight: bool,
     pub cost_price_per_unit: BigDecimal,
     pub selling_price_per_unit: BigDecimal,
     pub sale_end: Option<NaiveDateTime>,
-    pub buy_level: Option<f64>,
+    pub buy_level: Option<Quantity>,
     pub sale_price: Option<BigDecimal>,
+    #[serde(default)]
+    pub track_serials: bool,
+    #[serde(default)]
+    pub base_unit: Unit,
+    #[serde(default)]
+    pub purchase_unit: Unit,
+    #[serde(default)]
+    pub sale_unit: Unit,
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    /// The attributes a parent product's variants differ by, like size and flavor. Only
+    /// parent products have these.
+    #[serde(default)]
+    pub variant_attributes: Vec<String>,
+    #[serde(default)]
+    pub variant_options: Vec<VariantOption>,
+    /// What one of a kit is made of. Products that aren't kits have no components.
+    #[serde(default)]
+    pub components: Vec<KitComponent>,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct KitComponent {
+    pub product_id: i32,
+    pub amount: Quantity,
+}
+
+/// The value a variant has for one of its parent's attributes.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct VariantOption {
+    pub attribute: String,
+    pub value: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Each,
+    Case,
+    Kilogram,
+    Gram,
+    Pound,
+    Ounce,
+    Litre,
+    Millilitre,
+}
+
+impl Default for Unit {
+    fn default() -> Self {
+        Unit::Each
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
 pub struct Preference {
     pub user_id: i32,
+    /// How long removed records stay in the recycle bin. The app's default is used if unset.
+    #[serde(default)]
+    pub recycle_bin_days: Option<i32>,
 }
 
 #[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
@@ -74,14 +130,383 @@ pub struct ReceivedOrder {
     pub id: i32,
     pub received: Option<NaiveDateTime>,
     pub product_id: i32,
-    pub gross_amount: f64,
-    pub actually_received: f64,
-    pub damaged: f64,
+    pub gross_amount: Quantity,
+    pub actually_received: Quantity,
+    pub damaged: Quantity,
+    #[serde(default)]
+    pub supplier_id: Option<i32>,
+    #[serde(default)]
+    pub ordered: Option<NaiveDate>,
+    #[serde(default)]
+    pub expected: Option<NaiveDate>,
 }
 
 #[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
 pub struct PendingOrder {
     pub id: i32,
     pub product_id: i32,
-    pub amount: f64,
+    pub amount: Quantity,
+    #[serde(default)]
+    pub supplier_id: Option<i32>,
+    #[serde(default)]
+    pub cost_per_unit: Option<BigDecimal>,
+    #[serde(default)]
+    pub ordered: Option<NaiveDate>,
+    #[serde(default)]
+    pub expected: Option<NaiveDate>,
+}
+
+/// What a supplier sells a product as. Minimum order quantity and case pack are in the
+/// product's base unit.
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct SupplierItem {
+    pub id: i32,
+    pub supplier_id: i32,
+    pub product_id: i32,
+    pub supplier_sku: String,
+    pub cost_per_unit: BigDecimal,
+    pub min_order_quantity: Quantity,
+    pub case_pack: Option<Quantity>,
+    pub lead_time_days: i32,
+    pub preferred: bool,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct PriceChange {
+    pub id: i32,
+    pub product_id: i32,
+    pub user_name: String,
+    pub changed: NaiveDateTime,
+    pub old_cost_price: BigDecimal,
+    pub new_cost_price: BigDecimal,
+    pub old_selling_price: BigDecimal,
+    pub new_selling_price: BigDecimal,
+    pub reason: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustmentReason {
+    Opening,
+    Received,
+    Shrinkage,
+    Damage,
+    Theft,
+    FoundStock,
+    InternalUse,
+    Correction,
+    Count,
+    TransferOut,
+    TransferIn,
+    Assembly,
+    Disassembly,
+    ReturnedToVendor,
+    Sale,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct StockMovement {
+    pub id: i32,
+    pub product_id: i32,
+    #[serde(default)]
+    pub location_id: Option<i32>,
+    pub delta: Quantity,
+    pub reason: AdjustmentReason,
+    pub user_name: String,
+    pub note: String,
+    pub created: NaiveDateTime,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+pub enum AbcClass {
+    A,
+    B,
+    C,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CountScope {
+    Category(i32),
+    Brand(i32),
+    AbcClass(AbcClass),
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CountStatus {
+    Open,
+    Posted,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct CountLine {
+    pub product_id: i32,
+    pub name: String,
+    pub upc: String,
+    pub measure_by_weight: bool,
+    pub cost_price_per_unit: BigDecimal,
+    pub expected: Quantity,
+    pub counted: Option<Quantity>,
+    /// Set once the line's variance is in the ledger, so posting again after a failure
+    /// doesn't post it twice.
+    #[serde(default)]
+    pub posted: bool,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct CountSession {
+    pub id: i32,
+    pub scope: CountScope,
+    pub status: CountStatus,
+    pub user_name: String,
+    pub created: NaiveDateTime,
+    pub lines: Vec<CountLine>,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationKind {
+    Warehouse,
+    Store,
+    Bin,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct Location {
+    pub id: i32,
+    pub name: String,
+    pub kind: LocationKind,
+    pub parent_id: Option<i32>,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct LocationStock {
+    pub location_id: i32,
+    pub product_id: i32,
+    pub amount: Quantity,
+    pub buy_level: Option<Quantity>,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    Draft,
+    InTransit,
+    Received,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct TransferLine {
+    pub product_id: i32,
+    pub amount: Quantity,
+    /// Set once the line has left the sending location or reached the receiving one, so
+    /// shipping or receiving again after a failure doesn't post it twice.
+    #[serde(default)]
+    pub shipped: bool,
+    #[serde(default)]
+    pub received: bool,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct Transfer {
+    pub id: i32,
+    pub from_location_id: i32,
+    pub to_location_id: i32,
+    pub status: TransferStatus,
+    pub lines: Vec<TransferLine>,
+    pub created: NaiveDateTime,
+    pub shipped: Option<NaiveDateTime>,
+    pub received: Option<NaiveDateTime>,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct Lot {
+    pub id: i32,
+    pub product_id: i32,
+    pub received_order_id: Option<i32>,
+    pub lot_number: String,
+    pub best_before: Option<NaiveDate>,
+    pub received: NaiveDateTime,
+    pub amount: Quantity,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialStatus {
+    InStock,
+    Sold,
+    ReturnedToVendor,
+    WrittenOff,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct SerialEvent {
+    pub status: SerialStatus,
+    pub date: NaiveDateTime,
+    pub note: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct Serial {
+    pub id: i32,
+    pub product_id: i32,
+    pub serial: String,
+    pub received_order_id: Option<i32>,
+    pub supplier_id: Option<i32>,
+    pub received: NaiveDateTime,
+    pub status: SerialStatus,
+    pub events: Vec<SerialEvent>,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnStatus {
+    Requested,
+    Shipped,
+    Credited,
+}
+
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct ReturnLine {
+    pub product_id: i32,
+    pub received_order_id: Option<i32>,
+    pub amount: Quantity,
+    pub cost_per_unit: BigDecimal,
+    #[serde(default)]
+    pub serials: Vec<String>,
+}
+
+/// Goods going back to a supplier, usually because they arrived damaged.
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct VendorReturn {
+    pub id: i32,
+    pub supplier_id: i32,
+    pub location_id: Option<i32>,
+    pub rma_number: String,
+    pub status: ReturnStatus,
+    pub lines: Vec<ReturnLine>,
+    pub credit_expected: BigDecimal,
+    pub credit_received: Option<BigDecimal>,
+    pub note: String,
+    pub created: NaiveDateTime,
+    pub shipped: Option<NaiveDateTime>,
+    pub credited: Option<NaiveDateTime>,
+}
+
+/// One line of a sale. The quantity is in the product's base unit and the price is what the
+/// whole line sold for.
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct Sale {
+    pub id: i32,
+    pub product_id: i32,
+    pub location_id: Option<i32>,
+    pub quantity: Quantity,
+    pub total_price: BigDecimal,
+    pub sold: NaiveDateTime,
+    pub user_name: String,
+    #[serde(default)]
+    pub serials: Vec<String>,
+}
+
+/// A POS export that has been imported, kept so the same sales aren't imported twice.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct PosImport {
+    pub id: i32,
+    pub file_name: String,
+    pub file_hash: String,
+    pub imported: NaiveDateTime,
+    pub lines_imported: i32,
+    pub lines_skipped: i32,
+    /// Set while the file's sales are being posted.
+    #[serde(default)]
+    pub in_progress: bool,
+    /// Which lines have been posted, by receipt or by file and line, so a file that is
+    /// imported again, appended to or re-exported doesn't post them a second time.
+    #[serde(default)]
+    pub lines_posted: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A field that a change touched. A created record has no `before` and a deleted one no
+/// `after`.
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// Who changed what, and when. Each entry's hash covers the entry and the hash before it,
+/// so editing or deleting an entry breaks the chain from there on.
+#[derive(PartialEq, Debug, Deserialize, Serialize, Clone)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub user_name: String,
+    pub created: NaiveDateTime,
+    pub action: AuditAction,
+    pub entity: String,
+    pub entity_id: i32,
+    pub changes: Vec<FieldChange>,
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchivedEntity {
+    Product,
+    Brand,
+    Category,
+    Supplier,
+    PendingOrder,
+    ReceivedOrder,
+}
+
+/// A removed record in the recycle bin. The record itself is kept but hidden until it's
+/// restored, or deleted for good once it's been in the bin past the retention period.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
+pub struct ArchivedRecord {
+    pub id: i32,
+    pub entity: ArchivedEntity,
+    pub entity_id: i32,
+    pub name: String,
+    pub deleted: NaiveDateTime,
+    pub user_name: String,
+}
+
+#[cfg(test)]
+impl Product {
+    /// A product counted in each with nothing else set, for tests to adjust.
+    pub fn sample(id: i32, name: &str) -> Self {
+        Product {
+            id,
+            upc: String::new(),
+            name: name.to_string(),
+            description: String::new(),
+            amount: Quantity::zero(),
+            case_size: None,
+            measure_by_weight: false,
+            cost_price_per_unit: BigDecimal::from(0),
+            selling_price_per_unit: BigDecimal::from(0),
+            sale_end: None,
+            buy_level: None,
+            sale_price: None,
+            track_serials: false,
+            base_unit: Unit::Each,
+            purchase_unit: Unit::Each,
+            sale_unit: Unit::Each,
+            parent_id: None,
+            variant_attributes: Vec::new(),
+            variant_options: Vec::new(),
+            components: Vec::new(),
+        }
+    }
 }
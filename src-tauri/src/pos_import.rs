@@ -0,0 +1,401 @@
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use futures::lock::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Manager;
+
+use crate::client::Api;
+use crate::models::{ArchivedEntity, PosImport, Product};
+use crate::quantity::Quantity;
+use crate::recycle_bin;
+use crate::sales::{post_sale, AppSale};
+use crate::{err_to_string, AppState};
+
+/// Which columns of a POS export hold what, by header name. Without a price column lines
+/// sell at the product's current price, and without a timestamp column they're dated when
+/// they're imported. A receipt column tells lines apart across files; without one a line is
+/// known by its file name and line number.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PosColumns {
+    upc: String,
+    quantity: String,
+    #[serde(default)]
+    receipt: Option<String>,
+    #[serde(default)]
+    price: Option<String>,
+    #[serde(default)]
+    timestamp: Option<String>,
+    #[serde(default = "default_timestamp_format")]
+    timestamp_format: String,
+    #[serde(default = "default_delimiter")]
+    delimiter: char,
+}
+
+fn default_timestamp_format() -> String {
+    String::from("%m/%d/%Y %H:%M")
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PosProblem {
+    Unmatched,
+    Ambiguous,
+    Invalid,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PosLineProblem {
+    line: usize,
+    upc: String,
+    problem: PosProblem,
+    message: String,
+}
+
+/// What happened to a POS export. Lines with a problem aren't imported and have to be
+/// entered by hand.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PosImportReport {
+    file_name: String,
+    already_imported: bool,
+    lines_imported: usize,
+    lines_already_imported: usize,
+    problems: Vec<PosLineProblem>,
+}
+
+/// Set while a folder is being watched, and flipped to stop the watch.
+#[derive(Default)]
+pub struct PosWatchState(Mutex<Option<Arc<AtomicBool>>>);
+
+/// UPCs as tills print them, without spaces or leading zeros, so a UPC-A printed as an
+/// EAN-13 still matches.
+fn normalize_upc(upc: &str) -> String {
+    upc.chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .trim_start_matches('0')
+        .to_string()
+}
+
+/// Where a column is, looked up by its header without regard to case.
+pub fn column(headers: &csv::StringRecord, name: &str) -> Result<usize, Error> {
+    headers
+        .iter()
+        .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| anyhow!("File has no {} column", name))
+}
+
+fn parse_timestamp(value: &str, format: &str) -> Result<NaiveDateTime, Error> {
+    NaiveDateTime::parse_from_str(value, format)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, format).map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .with_context(|| format!("Can't read {} as a time", value))
+}
+
+struct PosLine {
+    line: usize,
+    /// What the line is known by once it's posted.
+    key: String,
+    upc: String,
+    quantity: String,
+    price: String,
+    timestamp: Option<String>,
+}
+
+pub fn csv_reader(bytes: &[u8], delimiter: char) -> Result<csv::Reader<&[u8]>, Error> {
+    if !delimiter.is_ascii() {
+        return Err(anyhow!("Delimiter has to be a single plain character"));
+    }
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(bytes))
+}
+
+fn read_lines(bytes: &[u8], file_name: &str, columns: &PosColumns) -> Result<Vec<PosLine>, Error> {
+    let mut reader = csv_reader(bytes, columns.delimiter)?;
+    let headers = reader.headers()?.clone();
+    let upc = column(&headers, &columns.upc)?;
+    let quantity = column(&headers, &columns.quantity)?;
+    let receipt = columns
+        .receipt
+        .as_ref()
+        .map(|name| column(&headers, name))
+        .transpose()?;
+    let price = columns
+        .price
+        .as_ref()
+        .map(|name| column(&headers, name))
+        .transpose()?;
+    let timestamp = columns
+        .timestamp
+        .as_ref()
+        .map(|name| column(&headers, name))
+        .transpose()?;
+    let mut lines = Vec::new();
+    // A receipt has a line per item, so its lines are numbered in the order they come.
+    let mut receipt_lines: HashMap<String, usize> = HashMap::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |column: usize| record.get(column).unwrap_or_default().to_string();
+        // Line 1 is the header.
+        let line = index + 2;
+        let key = match receipt.map(field) {
+            Some(receipt) if !receipt.is_empty() => {
+                let number = receipt_lines.entry(receipt.clone()).or_default();
+                *number += 1;
+                format!("receipt {} line {}", receipt, number)
+            }
+            _ => format!("{} line {}", file_name, line),
+        };
+        lines.push(PosLine {
+            line,
+            key,
+            upc: field(upc),
+            quantity: field(quantity),
+            price: price.map(field).unwrap_or_default(),
+            timestamp: timestamp.map(field),
+        });
+    }
+    Ok(lines)
+}
+
+/// Imports the lines of a POS export that haven't been imported before. Every line is
+/// checked before any sale is posted, and an import that stopped partway is finished.
+pub async fn import_file(
+    api: &Api,
+    path: &Path,
+    columns: &PosColumns,
+    location_id: Option<i32>,
+) -> Result<PosImportReport, Error> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let bytes = std::fs::read(path).with_context(|| format!("Can't read {}", file_name))?;
+    let file_hash = format!("{:x}", Sha256::digest(&bytes));
+    let previous = api.find_pos_imports(&file_hash).await?;
+    if previous.iter().any(|import| !import.in_progress) {
+        return Ok(PosImportReport {
+            file_name,
+            already_imported: true,
+            lines_imported: 0,
+            lines_already_imported: 0,
+            problems: Vec::new(),
+        });
+    }
+    let lines = read_lines(&bytes, &file_name, columns)?;
+    let posted = api
+        .all_pos_imports()
+        .await?
+        .into_iter()
+        .flat_map(|import| import.lines_posted)
+        .collect::<HashSet<_>>();
+    let (lines, already): (Vec<_>, Vec<_>) = lines
+        .into_iter()
+        .partition(|line| !posted.contains(&line.key));
+    let already_imported = lines.is_empty() && !already.is_empty();
+
+    let archived = recycle_bin::archived(api).await?;
+    let mut products: HashMap<String, Vec<Product>> = HashMap::new();
+    for product in api.all_products().await? {
+        let upc = normalize_upc(&product.upc);
+        if !upc.is_empty() && !archived.contains(ArchivedEntity::Product, product.id) {
+            products.entry(upc).or_default().push(product);
+        }
+    }
+    let now = Utc::now().naive_utc();
+    let mut sales = Vec::new();
+    let mut problems = Vec::new();
+    for line in lines {
+        let mut problem = |problem: PosProblem, message: String| {
+            problems.push(PosLineProblem {
+                line: line.line,
+                upc: line.upc.clone(),
+                problem,
+                message,
+            })
+        };
+        let product = match products.get(&normalize_upc(&line.upc)).map(Vec::as_slice) {
+            None | Some([]) => {
+                problem(
+                    PosProblem::Unmatched,
+                    String::from("No product has this UPC"),
+                );
+                continue;
+            }
+            Some([product]) => product,
+            Some(matches) => {
+                let names = matches
+                    .iter()
+                    .map(|product| product.name.as_str())
+                    .collect::<Vec<_>>();
+                problem(
+                    PosProblem::Ambiguous,
+                    format!("UPC belongs to {}", names.join(", ")),
+                );
+                continue;
+            }
+        };
+        let sale = Quantity::from_str(&line.quantity)
+            .context("Quantity is not a number")
+            .and_then(|quantity| {
+                let sold = match &line.timestamp {
+                    Some(timestamp) => parse_timestamp(timestamp, &columns.timestamp_format)?,
+                    None => now,
+                };
+                AppSale::new(product.id, location_id, quantity, line.price.clone(), sold)
+                    .to_sale(api, product)
+            });
+        match sale {
+            Ok(sale) => sales.push((line.key.clone(), sale)),
+            Err(err) => problem(PosProblem::Invalid, err.to_string()),
+        }
+    }
+
+    // The import is recorded before anything is posted and each posted line is marked on
+    // it, so if posting stops partway the next import skips what was posted.
+    let lines_imported = sales.len();
+    let mut import = match previous.into_iter().next() {
+        Some(import) => import,
+        None => {
+            let mut import = PosImport {
+                id: 0,
+                file_name: file_name.clone(),
+                file_hash,
+                imported: now,
+                lines_imported: lines_imported as i32,
+                lines_skipped: problems.len() as i32,
+                in_progress: true,
+                lines_posted: Vec::new(),
+            };
+            import.id = api.new_pos_import(&import).await?;
+            import
+        }
+    };
+    for (key, sale) in sales {
+        post_sale(api, sale).await?;
+        import.lines_posted.push(key);
+        api.update_pos_import(&import).await?;
+    }
+    import.in_progress = false;
+    api.update_pos_import(&import).await?;
+    Ok(PosImportReport {
+        file_name,
+        already_imported,
+        lines_imported,
+        lines_already_imported: already.len(),
+        problems,
+    })
+}
+
+/// Whether a file was changed within the given time, so the till may still be writing it.
+fn recently_modified(path: &Path, within: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_or(true, |modified| {
+            modified.elapsed().map_or(true, |elapsed| elapsed < within)
+        })
+}
+
+/// The exports in a folder that haven't changed for `settled`, oldest name first.
+fn export_files(folder: &Path, settled: Duration) -> Result<Vec<PathBuf>, Error> {
+    let mut files = std::fs::read_dir(folder)
+        .with_context(|| format!("Can't read folder {}", folder.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().map_or(false, |extension| {
+                    extension.eq_ignore_ascii_case("csv") || extension.eq_ignore_ascii_case("txt")
+                })
+                && !recently_modified(path, settled)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+#[tauri::command]
+pub async fn import_pos_file(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    columns: PosColumns,
+    location_id: Option<i32>,
+) -> Result<PosImportReport, String> {
+    let api = state.0.lock().await;
+    import_file(&api, Path::new(&path), &columns, location_id)
+        .await
+        .map_err(err_to_string)
+}
+
+/// Imports every export that shows up in a folder, checking again every
+/// `interval_seconds`. Files changed since the last check are left until the next one, so a
+/// file still being written isn't read halfway. Each new import is sent to the window as a
+/// `pos_import` event. Watching another folder stops the previous watch.
+#[tauri::command]
+pub async fn watch_pos_folder(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    watch: tauri::State<'_, PosWatchState>,
+    folder: String,
+    columns: PosColumns,
+    location_id: Option<i32>,
+    interval_seconds: Option<u64>,
+) -> Result<(), String> {
+    let folder = PathBuf::from(folder);
+    if !folder.is_dir() {
+        return Err(format!("{} isn't a folder", folder.display()));
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(previous) = watch.0.lock().await.replace(stop.clone()) {
+        previous.store(true, Ordering::Relaxed);
+    }
+    let api = state.0.clone();
+    let interval = Duration::from_secs(interval_seconds.unwrap_or(60).max(1));
+    tokio::spawn(async move {
+        while !stop.load(Ordering::Relaxed) {
+            let files = match export_files(&folder, interval) {
+                Ok(files) => files,
+                Err(err) => {
+                    app.emit_all("pos_import_error", err.to_string()).ok();
+                    Vec::new()
+                }
+            };
+            for file in files {
+                let api = api.lock().await;
+                match import_file(&api, &file, &columns, location_id).await {
+                    Ok(report) if report.already_imported => {}
+                    Ok(report) => {
+                        app.emit_all("pos_import", report).ok();
+                    }
+                    Err(err) => {
+                        app.emit_all("pos_import_error", format!("{}: {}", file.display(), err))
+                            .ok();
+                    }
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_pos_watch(watch: tauri::State<'_, PosWatchState>) -> Result<(), String> {
+    if let Some(stop) = watch.0.lock().await.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
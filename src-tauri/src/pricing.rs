@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::client::Api;
+use crate::models::{ArchivedEntity, PriceChange, Product};
+use crate::{audit, err_to_string, recycle_bin, AppState};
+
+#[derive(Clone, Deserialize, Serialize, Default)]
+pub struct AppPriceChange {
+    id: i32,
+    product_id: i32,
+    user_name: String,
+    changed: String,
+    old_cost_price: String,
+    new_cost_price: String,
+    old_selling_price: String,
+    new_selling_price: String,
+    reason: String,
+}
+
+impl AppPriceChange {
+    fn from_change(change: PriceChange) -> Self {
+        AppPriceChange {
+            id: change.id,
+            product_id: change.product_id,
+            user_name: change.user_name,
+            changed: change.changed.format("%m/%d/%Y %H:%M").to_string(),
+            old_cost_price: change.old_cost_price.to_string(),
+            new_cost_price: change.new_cost_price.to_string(),
+            old_selling_price: change.old_selling_price.to_string(),
+            new_selling_price: change.new_selling_price.to_string(),
+            reason: change.reason,
+        }
+    }
+}
+
+/// Which group of products a bulk price increase applies to.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceTarget {
+    Supplier(i32),
+    Brand(i32),
+}
+
+/// Which of the product's prices a bulk price increase changes.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceField {
+    Cost,
+    Selling,
+    Both,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug, PartialEq, Eq)]
+pub struct PriceDiff {
+    product_id: i32,
+    name: String,
+    old_cost_price: String,
+    new_cost_price: String,
+    old_selling_price: String,
+    new_selling_price: String,
+}
+
+impl PriceDiff {
+    fn new(before: &Product, after: &Product) -> Self {
+        PriceDiff {
+            product_id: before.id,
+            name: before.name.clone(),
+            old_cost_price: before.cost_price_per_unit.to_string(),
+            new_cost_price: after.cost_price_per_unit.to_string(),
+            old_selling_price: before.selling_price_per_unit.to_string(),
+            new_selling_price: after.selling_price_per_unit.to_string(),
+        }
+    }
+}
+
+/// Records a price change if `before` and `after` differ in either price.
+pub async fn record_price_change(
+    api: &Api,
+    before: &Product,
+    after: &Product,
+    reason: &str,
+) -> Result<(), Error> {
+    if before.cost_price_per_unit == after.cost_price_per_unit
+        && before.selling_price_per_unit == after.selling_price_per_unit
+    {
+        return Ok(());
+    }
+    api.new_price_change(&PriceChange {
+        id: 0,
+        product_id: after.id,
+        user_name: api.user_name().to_string(),
+        changed: Utc::now().naive_utc(),
+        old_cost_price: before.cost_price_per_unit.clone(),
+        new_cost_price: after.cost_price_per_unit.clone(),
+        old_selling_price: before.selling_price_per_unit.clone(),
+        new_selling_price: after.selling_price_per_unit.clone(),
+        reason: reason.to_string(),
+    })
+    .await?;
+    Ok(())
+}
+
+/// Multiplies a price by a factor and rounds it to whole cents.
+fn increase(price: &BigDecimal, factor: &BigDecimal) -> BigDecimal {
+    (price * factor).round(2)
+}
+
+/// The products a price increase applies to. Products in the recycle bin keep their prices.
+async fn target_products(api: &Api, target: PriceTarget) -> Result<Vec<Product>, Error> {
+    let ids = match target {
+        PriceTarget::Supplier(id) => api.get_supplier(id).await?.products,
+        PriceTarget::Brand(id) => api.get_brand(id).await?.products,
+    };
+    let archived = recycle_bin::archived(api).await?;
+    let mut products = Vec::new();
+    for id in ids.into_iter().flatten() {
+        if archived.contains(ArchivedEntity::Product, id) {
+            continue;
+        }
+        products.push(api.get_product(id).await?);
+    }
+    Ok(products)
+}
+
+/// Computes the products affected by a percentage increase together with their new prices.
+async fn price_increase(
+    api: &Api,
+    target: PriceTarget,
+    field: PriceField,
+    percent: &str,
+) -> Result<Vec<(Product, Product)>, Error> {
+    let percent = BigDecimal::from_str(percent).context("Percentage is not a number")?;
+    let factor = BigDecimal::from(1) + percent / BigDecimal::from(100);
+    if factor < BigDecimal::zero() {
+        return Err(anyhow!("Prices can't drop below zero"));
+    }
+    Ok(target_products(api, target)
+        .await?
+        .into_iter()
+        .map(|before| {
+            let mut after = before.clone();
+            if field != PriceField::Selling {
+                after.cost_price_per_unit = increase(&before.cost_price_per_unit, &factor);
+            }
+            if field != PriceField::Cost {
+                after.selling_price_per_unit = increase(&before.selling_price_per_unit, &factor);
+            }
+            (before, after)
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn price_history(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+) -> Result<Vec<AppPriceChange>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .get_price_history(product_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(AppPriceChange::from_change)
+        .collect())
+}
+
+#[tauri::command]
+pub async fn preview_price_increase(
+    state: tauri::State<'_, AppState>,
+    target: PriceTarget,
+    field: PriceField,
+    percent: &str,
+) -> Result<Vec<PriceDiff>, String> {
+    let api = state.0.lock().await;
+    Ok(price_increase(&api, target, field, percent)
+        .await
+        .map_err(err_to_string)?
+        .iter()
+        .map(|(before, after)| PriceDiff::new(before, after))
+        .collect())
+}
+
+/// Applies an increase the user has confirmed. `preview` is what `preview_price_increase`
+/// showed, and nothing is changed if the prices have moved since.
+#[tauri::command]
+pub async fn apply_price_increase(
+    state: tauri::State<'_, AppState>,
+    target: PriceTarget,
+    field: PriceField,
+    percent: &str,
+    reason: String,
+    preview: Vec<PriceDiff>,
+) -> Result<Vec<PriceDiff>, String> {
+    let api = state.0.lock().await;
+    let changes = price_increase(&api, target, field, percent)
+        .await
+        .map_err(err_to_string)?;
+    let diffs = changes
+        .iter()
+        .map(|(before, after)| PriceDiff::new(before, after))
+        .collect::<Vec<_>>();
+    if diffs != preview {
+        return Err(String::from(
+            "Prices have changed since the preview, so preview the increase again",
+        ));
+    }
+    let mut applied = Vec::new();
+    for (before, after) in changes {
+        api.update_product(&after).await.map_err(err_to_string)?;
+        record_price_change(&api, &before, &after, &reason)
+            .await
+            .map_err(err_to_string)?;
+        audit::updated(&api, "product", after.id, &before, &after)
+            .await
+            .map_err(err_to_string)?;
+        applied.push(PriceDiff::new(&before, &after));
+    }
+    Ok(applied)
+}
@@ -0,0 +1,432 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::audit;
+use crate::client::Api;
+use crate::models::ArchivedEntity;
+use crate::pos_import::{column, csv_reader};
+use crate::quantity::Quantity;
+use crate::recycle_bin::{self, Archived};
+use crate::{err_to_string, AppState};
+
+/// Which columns of a product file hold what, by header name. Only UPC and name are
+/// required. Categories and suppliers can list several names split by `list_separator`.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ProductColumns {
+    upc: String,
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    cost_price: Option<String>,
+    #[serde(default)]
+    selling_price: Option<String>,
+    #[serde(default)]
+    buy_level: Option<String>,
+    #[serde(default)]
+    case_size: Option<String>,
+    #[serde(default)]
+    measure_by_weight: Option<String>,
+    #[serde(default)]
+    brand: Option<String>,
+    #[serde(default)]
+    categories: Option<String>,
+    #[serde(default)]
+    suppliers: Option<String>,
+    #[serde(default = "default_delimiter")]
+    delimiter: char,
+    #[serde(default = "default_list_separator")]
+    list_separator: char,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_list_separator() -> char {
+    '|'
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ProductImportRow {
+    line: usize,
+    upc: String,
+    name: String,
+    product_id: Option<i32>,
+    errors: Vec<String>,
+}
+
+/// What an import did, or would do on a dry run. Brands, categories and suppliers are
+/// matched by name and the ones listed here are new.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ProductImportReport {
+    dry_run: bool,
+    created: usize,
+    rows: Vec<ProductImportRow>,
+    new_brands: Vec<String>,
+    new_categories: Vec<String>,
+    new_suppliers: Vec<String>,
+}
+
+struct ProductRow {
+    upc: String,
+    name: String,
+    description: String,
+    cost_price: BigDecimal,
+    selling_price: BigDecimal,
+    buy_level: Quantity,
+    case_size: Option<i32>,
+    measure_by_weight: bool,
+    brand: Option<String>,
+    categories: Vec<String>,
+    suppliers: Vec<String>,
+}
+
+/// Checks the length and check digit of a UPC-A, EAN-8, EAN-13 or GTIN-14.
+pub fn check_upc(upc: &str) -> Result<(), Error> {
+    if !upc.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow!("UPC {} can only have digits", upc));
+    }
+    if ![8, 12, 13, 14].contains(&upc.len()) {
+        return Err(anyhow!("UPC {} has the wrong number of digits", upc));
+    }
+    let digits = upc
+        .bytes()
+        .map(|digit| u32::from(digit - b'0'))
+        .collect::<Vec<_>>();
+    let (check, body) = digits.split_last().unwrap();
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { digit * 3 } else { *digit })
+        .sum();
+    if (10 - sum % 10) % 10 != *check {
+        return Err(anyhow!("UPC {} has a wrong check digit", upc));
+    }
+    Ok(())
+}
+
+fn parse_price(value: &str, what: &str) -> Result<BigDecimal, Error> {
+    if value.is_empty() {
+        return Ok(BigDecimal::zero());
+    }
+    let price = BigDecimal::from_str(value.trim_start_matches('$'))
+        .with_context(|| format!("{} {} is not a number", what, value))?;
+    if price < BigDecimal::zero() {
+        return Err(anyhow!("{} can't be negative", what));
+    }
+    Ok(price)
+}
+
+fn parse_bool(value: &str) -> Result<bool, Error> {
+    match value.to_lowercase().as_str() {
+        "" | "no" | "n" | "false" | "0" => Ok(false),
+        "yes" | "y" | "true" | "1" => Ok(true),
+        _ => Err(anyhow!("{} isn't yes or no", value)),
+    }
+}
+
+fn split_names(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Looks names up without regard to case, and remembers the ones that have to be created.
+struct Names {
+    ids: HashMap<String, i32>,
+    new: Vec<String>,
+}
+
+impl Names {
+    /// Names to match against, leaving out anything in the recycle bin.
+    fn new(existing: Vec<(String, i32)>, archived: &Archived, entity: ArchivedEntity) -> Self {
+        Names {
+            ids: existing
+                .into_iter()
+                .filter(|(_, id)| !archived.contains(entity, *id))
+                .map(|(name, id)| (name.trim().to_lowercase(), id))
+                .collect(),
+            new: Vec::new(),
+        }
+    }
+
+    fn note(&mut self, name: &str) {
+        let key = name.to_lowercase();
+        if !self.ids.contains_key(&key) && !self.new.iter().any(|new| new.to_lowercase() == key) {
+            self.new.push(name.to_string());
+        }
+    }
+
+    fn id(&self, name: &str) -> Option<i32> {
+        self.ids.get(&name.to_lowercase()).copied()
+    }
+}
+
+fn ok<T>(result: Result<T, Error>, errors: &mut Vec<String>) -> Option<T> {
+    result.map_err(|err| errors.push(err.to_string())).ok()
+}
+
+/// Reads every row of a file. Rows that can't be made into a product come back with their
+/// errors and without a product.
+fn read_rows(
+    bytes: &[u8],
+    columns: &ProductColumns,
+) -> Result<Vec<(ProductImportRow, Option<ProductRow>)>, Error> {
+    let mut reader = csv_reader(bytes, columns.delimiter)?;
+    let headers = reader.headers()?.clone();
+    let optional =
+        |name: &Option<String>| name.as_ref().map(|name| column(&headers, name)).transpose();
+    let upc = column(&headers, &columns.upc)?;
+    let name = column(&headers, &columns.name)?;
+    let description = optional(&columns.description)?;
+    let cost_price = optional(&columns.cost_price)?;
+    let selling_price = optional(&columns.selling_price)?;
+    let buy_level = optional(&columns.buy_level)?;
+    let case_size = optional(&columns.case_size)?;
+    let measure_by_weight = optional(&columns.measure_by_weight)?;
+    let brand = optional(&columns.brand)?;
+    let categories = optional(&columns.categories)?;
+    let suppliers = optional(&columns.suppliers)?;
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .unwrap_or_default()
+                .to_string()
+        };
+        let mut row = ProductImportRow {
+            // Line 1 is the header.
+            line: index + 2,
+            upc: field(Some(upc)),
+            name: field(Some(name)),
+            product_id: None,
+            errors: Vec::new(),
+        };
+        let errors = &mut row.errors;
+        if !row.upc.is_empty() {
+            ok(check_upc(&row.upc), errors);
+        }
+        if row.name.is_empty() {
+            errors.push(String::from("Name can't be blank"));
+        }
+        let row_cost_price = ok(parse_price(&field(cost_price), "Cost price"), errors);
+        let row_selling_price = ok(parse_price(&field(selling_price), "Selling price"), errors);
+        let row_buy_level = ok(
+            match field(buy_level).as_str() {
+                "" => Ok(Quantity::zero()),
+                value => Quantity::from_str(value)
+                    .with_context(|| format!("Buy level {} is not a number", value)),
+            },
+            errors,
+        );
+        let row_case_size = ok(
+            match field(case_size).as_str() {
+                "" => Ok(None),
+                value => i32::from_str(value)
+                    .map(Some)
+                    .with_context(|| format!("Case size {} is not a whole number", value)),
+            },
+            errors,
+        );
+        let row_measure_by_weight = ok(parse_bool(&field(measure_by_weight)), errors);
+        let product = match (
+            row_cost_price,
+            row_selling_price,
+            row_buy_level,
+            row_case_size,
+            row_measure_by_weight,
+        ) {
+            (
+                Some(cost_price),
+                Some(selling_price),
+                Some(buy_level),
+                Some(case_size),
+                Some(measure_by_weight),
+            ) if errors.is_empty() => {
+                let brand = field(brand);
+                Some(ProductRow {
+                    upc: row.upc.clone(),
+                    name: row.name.clone(),
+                    description: field(description),
+                    cost_price,
+                    selling_price,
+                    buy_level,
+                    case_size,
+                    measure_by_weight,
+                    brand: if brand.is_empty() { None } else { Some(brand) },
+                    categories: split_names(&field(categories), columns.list_separator),
+                    suppliers: split_names(&field(suppliers), columns.list_separator),
+                })
+            }
+            _ => None,
+        };
+        rows.push((row, product));
+    }
+    Ok(rows)
+}
+
+/// The headers of a file, so the columns can be mapped before importing.
+#[tauri::command]
+pub async fn product_csv_headers(
+    path: String,
+    delimiter: Option<char>,
+) -> Result<Vec<String>, String> {
+    let bytes = std::fs::read(&path)
+        .with_context(|| format!("Can't read {}", path))
+        .map_err(err_to_string)?;
+    let mut reader =
+        csv_reader(&bytes, delimiter.unwrap_or_else(default_delimiter)).map_err(err_to_string)?;
+    Ok(reader
+        .headers()
+        .context("Can't read the header line")
+        .map_err(err_to_string)?
+        .iter()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Creates a product for every row of a file. A dry run only checks the rows. Rows with
+/// errors, or with a UPC that is taken, stop the whole import unless `skip_invalid` is
+/// set, in which case only the good rows are imported.
+#[tauri::command]
+pub async fn import_products_csv(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    columns: ProductColumns,
+    dry_run: bool,
+    skip_invalid: Option<bool>,
+) -> Result<ProductImportReport, String> {
+    let bytes = std::fs::read(&path)
+        .with_context(|| format!("Can't read {}", path))
+        .map_err(err_to_string)?;
+    let rows = read_rows(&bytes, &columns).map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    import_rows(&api, rows, dry_run, skip_invalid.unwrap_or(false))
+        .await
+        .map_err(err_to_string)
+}
+
+async fn import_rows(
+    api: &Api,
+    rows: Vec<(ProductImportRow, Option<ProductRow>)>,
+    dry_run: bool,
+    skip_invalid: bool,
+) -> Result<ProductImportReport, Error> {
+    let taken = api
+        .product_names()
+        .await?
+        .into_iter()
+        .filter(|(_, upc, _)| !upc.is_empty())
+        .map(|(name, upc, _)| (upc, name))
+        .collect::<HashMap<_, _>>();
+    let mut seen = HashSet::new();
+    let mut report_rows = Vec::new();
+    let mut valid = Vec::new();
+    for (mut row, product) in rows {
+        if let Some(product) = product {
+            if product.upc.is_empty() {
+                valid.push((report_rows.len(), product));
+            } else if let Some(name) = taken.get(&product.upc) {
+                row.errors.push(format!("UPC already belongs to {}", name));
+            } else if !seen.insert(product.upc.clone()) {
+                row.errors
+                    .push(format!("UPC {} is in the file more than once", product.upc));
+            } else {
+                valid.push((report_rows.len(), product));
+            }
+        }
+        report_rows.push(row);
+    }
+
+    let archived = recycle_bin::archived(api).await?;
+    let mut brands = Names::new(api.brand_names().await?, &archived, ArchivedEntity::Brand);
+    let mut categories = Names::new(
+        api.category_names().await?,
+        &archived,
+        ArchivedEntity::Category,
+    );
+    let mut suppliers = Names::new(
+        api.supplier_names().await?,
+        &archived,
+        ArchivedEntity::Supplier,
+    );
+    for (_, row) in &valid {
+        if let Some(brand) = &row.brand {
+            brands.note(brand);
+        }
+        row.categories.iter().for_each(|name| categories.note(name));
+        row.suppliers.iter().for_each(|name| suppliers.note(name));
+    }
+    let has_errors = report_rows.iter().any(|row| !row.errors.is_empty());
+    let mut report = ProductImportReport {
+        dry_run,
+        created: 0,
+        rows: Vec::new(),
+        new_brands: brands.new.clone(),
+        new_categories: categories.new.clone(),
+        new_suppliers: suppliers.new.clone(),
+    };
+    if dry_run || (has_errors && !skip_invalid) {
+        report.rows = report_rows;
+        return Ok(report);
+    }
+
+    for name in brands.new.clone() {
+        let id = api.new_brand(&name).await?;
+        audit::created(api, "brand", id, &api.get_brand(id).await?).await?;
+        brands.ids.insert(name.to_lowercase(), id);
+    }
+    for name in categories.new.clone() {
+        let id = api.new_category(&name).await?;
+        audit::created(api, "category", id, &api.get_category(id).await?).await?;
+        categories.ids.insert(name.to_lowercase(), id);
+    }
+    for name in suppliers.new.clone() {
+        let id = api.new_supplier(&name, "", "").await?;
+        audit::created(api, "supplier", id, &api.get_supplier(id).await?).await?;
+        suppliers.ids.insert(name.to_lowercase(), id);
+    }
+    for (index, row) in valid {
+        let id = api
+            .new_product(
+                &row.upc,
+                &row.name,
+                &row.description,
+                row.measure_by_weight,
+                row.cost_price,
+                row.selling_price,
+                row.buy_level,
+                row.categories
+                    .iter()
+                    .filter_map(|name| categories.id(name))
+                    .collect(),
+                row.suppliers
+                    .iter()
+                    .filter_map(|name| suppliers.id(name))
+                    .collect(),
+                row.brand.as_ref().and_then(|name| brands.id(name)),
+            )
+            .await?;
+        let mut product = api.get_product(id).await?;
+        if row.case_size.is_some() {
+            product.case_size = row.case_size;
+            api.update_product(&product).await?;
+        }
+        audit::created(api, "product", id, &product).await?;
+        report_rows[index].product_id = Some(id);
+        report.created += 1;
+    }
+    report.rows = report_rows;
+    Ok(report)
+}
@@ -0,0 +1,187 @@
+use bigdecimal::{BigDecimal, Signed, Zero};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// An exact stock quantity. Quantities go over the wire as plain JSON numbers, the same as
+/// the `f64`s they replaced, and strings holding a number are accepted as well.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Default)]
+pub struct Quantity(BigDecimal);
+
+impl Quantity {
+    pub fn zero() -> Self {
+        Quantity(BigDecimal::zero())
+    }
+
+    pub fn decimal(&self) -> &BigDecimal {
+        &self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.0.is_positive()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0.is_negative()
+    }
+
+    pub fn is_whole(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    pub fn abs(&self) -> Self {
+        Quantity(self.0.abs())
+    }
+
+    /// Rounds to the given number of decimal places. Only the next digit decides the rounding,
+    /// so the rest is cut first: the decimal type can't round a quotient carried out to a
+    /// hundred digits.
+    pub fn round(&self, places: i64) -> Self {
+        Quantity(self.0.with_scale(places + 1).round(places))
+    }
+}
+
+impl From<BigDecimal> for Quantity {
+    fn from(decimal: BigDecimal) -> Self {
+        Quantity(decimal)
+    }
+}
+
+impl From<i32> for Quantity {
+    fn from(whole: i32) -> Self {
+        Quantity(BigDecimal::from(whole))
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = bigdecimal::ParseBigDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Quantity(BigDecimal::from_str(s.trim())?))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_zero() {
+            // Zero can come back with a scale, which would print as 0E-3
+            return write!(f, "0");
+        }
+        write!(f, "{}", self.0.normalized())
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde_json::Number::from_str(&self.to_string())
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Number(number) => {
+                Quantity::from_str(&number.to_string()).map_err(de::Error::custom)
+            }
+            serde_json::Value::String(string) => {
+                Quantity::from_str(&string).map_err(de::Error::custom)
+            }
+            other => Err(de::Error::custom(format!(
+                "expected a quantity, found {}",
+                other
+            ))),
+        }
+    }
+}
+
+impl Add for Quantity {
+    type Output = Quantity;
+
+    fn add(self, other: Quantity) -> Quantity {
+        Quantity(self.0 + other.0)
+    }
+}
+
+impl<'a> Add<&'a Quantity> for &'a Quantity {
+    type Output = Quantity;
+
+    fn add(self, other: &Quantity) -> Quantity {
+        Quantity(&self.0 + &other.0)
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Quantity;
+
+    fn sub(self, other: Quantity) -> Quantity {
+        Quantity(self.0 - other.0)
+    }
+}
+
+impl<'a> Sub<&'a Quantity> for &'a Quantity {
+    type Output = Quantity;
+
+    fn sub(self, other: &Quantity) -> Quantity {
+        Quantity(&self.0 - &other.0)
+    }
+}
+
+impl Neg for Quantity {
+    type Output = Quantity;
+
+    fn neg(self) -> Quantity {
+        Quantity(-self.0)
+    }
+}
+
+impl Neg for &Quantity {
+    type Output = Quantity;
+
+    fn neg(self) -> Quantity {
+        Quantity(-&self.0)
+    }
+}
+
+impl AddAssign for Quantity {
+    fn add_assign(&mut self, other: Quantity) {
+        self.0 += other.0;
+    }
+}
+
+impl AddAssign<&Quantity> for Quantity {
+    fn add_assign(&mut self, other: &Quantity) {
+        self.0 += &other.0;
+    }
+}
+
+impl SubAssign for Quantity {
+    fn sub_assign(&mut self, other: Quantity) {
+        self.0 -= other.0;
+    }
+}
+
+impl SubAssign<&Quantity> for Quantity {
+    fn sub_assign(&mut self, other: &Quantity) {
+        self.0 -= &other.0;
+    }
+}
+
+impl Sum for Quantity {
+    fn sum<I: Iterator<Item = Quantity>>(iter: I) -> Quantity {
+        iter.fold(Quantity::zero(), |total, quantity| total + quantity)
+    }
+}
+
+impl<'a> Sum<&'a Quantity> for Quantity {
+    fn sum<I: Iterator<Item = &'a Quantity>>(iter: I) -> Quantity {
+        iter.fold(Quantity::zero(), |total, quantity| total + quantity.clone())
+    }
+}
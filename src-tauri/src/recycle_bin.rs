@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Error};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use crate::audit;
+use crate::client::Api;
+use crate::models::{ArchivedEntity, ArchivedRecord};
+use crate::{err_to_string, AppState};
+
+/// How long removed records stay in the recycle bin unless the user's preference says
+/// otherwise.
+const DEFAULT_RETENTION_DAYS: i32 = 30;
+
+/// How many records are read at a time while skipping removed ones.
+const PAGE_SIZE: i64 = 100;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppArchivedRecord {
+    id: i32,
+    entity: ArchivedEntity,
+    entity_id: i32,
+    name: String,
+    deleted: String,
+    purge_on: String,
+    user_name: String,
+}
+
+/// The ids of removed records, by entity.
+#[derive(Default)]
+pub struct Archived(HashMap<ArchivedEntity, HashSet<i32>>);
+
+impl Archived {
+    pub fn contains(&self, entity: ArchivedEntity, id: i32) -> bool {
+        self.0.get(&entity).map_or(false, |ids| ids.contains(&id))
+    }
+}
+
+pub async fn archived(api: &Api) -> Result<Archived, Error> {
+    let mut archived = Archived::default();
+    for record in api.get_archived_records().await? {
+        archived
+            .0
+            .entry(record.entity)
+            .or_default()
+            .insert(record.entity_id);
+    }
+    Ok(archived)
+}
+
+/// A page of a list with the removed records left out. The list is read from the start until
+/// there are enough records that aren't removed.
+pub async fn visible_page<T, F, Fut>(
+    limit: i64,
+    offset: i64,
+    hidden: impl Fn(&T) -> bool,
+    mut page: F,
+) -> Result<Vec<T>, Error>
+where
+    F: FnMut(i64, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Error>>,
+{
+    let wanted = (offset + limit) as usize;
+    let mut visible = Vec::new();
+    let mut read = 0;
+    while visible.len() < wanted {
+        let next = page(PAGE_SIZE, read).await?;
+        let done = (next.len() as i64) < PAGE_SIZE;
+        read += next.len() as i64;
+        visible.extend(next.into_iter().filter(|item| !hidden(item)));
+        if done {
+            break;
+        }
+    }
+    Ok(visible
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect())
+}
+
+/// Puts a record in the recycle bin.
+pub async fn archive(
+    api: &Api,
+    entity: ArchivedEntity,
+    entity_id: i32,
+    name: &str,
+) -> Result<(), Error> {
+    if find(api, entity, entity_id).await?.is_some() {
+        return Err(anyhow!("That's already in the recycle bin"));
+    }
+    api.new_archived_record(&ArchivedRecord {
+        id: 0,
+        entity,
+        entity_id,
+        name: name.to_string(),
+        deleted: Utc::now().naive_utc(),
+        user_name: api.user_name().to_string(),
+    })
+    .await?;
+    Ok(())
+}
+
+/// Takes a record out of the recycle bin.
+pub async fn unarchive(api: &Api, entity: ArchivedEntity, entity_id: i32) -> Result<(), Error> {
+    let record = find(api, entity, entity_id)
+        .await?
+        .ok_or_else(|| anyhow!("That isn't in the recycle bin"))?;
+    api.remove_archived_record(record.id).await
+}
+
+async fn find(
+    api: &Api,
+    entity: ArchivedEntity,
+    entity_id: i32,
+) -> Result<Option<ArchivedRecord>, Error> {
+    Ok(api
+        .get_archived_records()
+        .await?
+        .into_iter()
+        .find(|record| record.entity == entity && record.entity_id == entity_id))
+}
+
+async fn retention_days(api: &Api) -> Result<i32, Error> {
+    Ok(api
+        .get_preference()
+        .await?
+        .recycle_bin_days
+        .unwrap_or(DEFAULT_RETENTION_DAYS))
+}
+
+/// Deletes a removed record for good.
+async fn purge(api: &Api, record: &ArchivedRecord) -> Result<(), Error> {
+    match record.entity {
+        ArchivedEntity::Product => api.remove_product(record.entity_id).await?,
+        ArchivedEntity::Brand => api.remove_brand(record.entity_id).await?,
+        ArchivedEntity::Category => api.remove_category(record.entity_id).await?,
+        ArchivedEntity::Supplier => api.remove_supplier(record.entity_id).await?,
+        ArchivedEntity::PendingOrder => api.remove_pending_order(record.entity_id).await?,
+        ArchivedEntity::ReceivedOrder => api.remove_received_order(record.entity_id).await?,
+    }
+    api.remove_archived_record(record.id).await?;
+    audit::deleted(api, "archived_record", record.id, record).await
+}
+
+impl AppArchivedRecord {
+    fn from_record(record: ArchivedRecord, retention_days: i32) -> Self {
+        AppArchivedRecord {
+            id: record.id,
+            entity: record.entity,
+            entity_id: record.entity_id,
+            name: record.name,
+            deleted: record.deleted.format("%m/%d/%Y %H:%M").to_string(),
+            purge_on: (record.deleted + Duration::days(retention_days as i64))
+                .format("%m/%d/%Y")
+                .to_string(),
+            user_name: record.user_name,
+        }
+    }
+}
+
+/// What's in the recycle bin, newest first. Records that have been there longer than the
+/// retention period are deleted for good first.
+#[tauri::command]
+pub async fn recycle_bin(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<AppArchivedRecord>, String> {
+    let api = state.0.lock().await;
+    let retention_days = retention_days(&api).await.map_err(err_to_string)?;
+    let cutoff = Utc::now().naive_utc() - Duration::days(retention_days as i64);
+    let mut kept = Vec::new();
+    for record in api.get_archived_records().await.map_err(err_to_string)? {
+        if record.deleted < cutoff {
+            purge(&api, &record).await.map_err(err_to_string)?;
+        } else {
+            kept.push(record);
+        }
+    }
+    kept.sort_by_key(|record| std::cmp::Reverse(record.deleted));
+    Ok(kept
+        .into_iter()
+        .map(|record| AppArchivedRecord::from_record(record, retention_days))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn restore_from_recycle_bin(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let record = api
+        .get_archived_records()
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .find(|record| record.id == id)
+        .ok_or_else(|| String::from("That isn't in the recycle bin"))?;
+    api.remove_archived_record(id)
+        .await
+        .map_err(err_to_string)?;
+    audit::deleted(&api, "archived_record", id, &record)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+/// Deletes a record in the recycle bin for good without waiting for it to expire.
+#[tauri::command]
+pub async fn purge_from_recycle_bin(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let record = api
+        .get_archived_records()
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .find(|record| record.id == id)
+        .ok_or_else(|| String::from("That isn't in the recycle bin"))?;
+    purge(&api, &record).await.map_err(err_to_string)
+}
+
+#[tauri::command]
+pub async fn recycle_bin_retention(state: tauri::State<'_, AppState>) -> Result<i32, String> {
+    retention_days(&*state.0.lock().await)
+        .await
+        .map_err(err_to_string)
+}
+
+#[tauri::command]
+pub async fn set_recycle_bin_retention(
+    state: tauri::State<'_, AppState>,
+    days: i32,
+) -> Result<(), String> {
+    if days < 1 {
+        return Err(String::from("Retention has to be at least a day"));
+    }
+    let api = state.0.lock().await;
+    let mut preference = api.get_preference().await.map_err(err_to_string)?;
+    preference.recycle_bin_days = Some(days);
+    api.update_preference(&preference)
+        .await
+        .map_err(err_to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    /// Pages through the numbers below `total`, hiding multiples of `hidden_every`, and
+    /// counts how many pages were read.
+    fn page_of(total: i32, hidden_every: i32, limit: i64, offset: i64) -> (Vec<i32>, usize) {
+        let mut reads = 0;
+        let page = block_on(visible_page(
+            limit,
+            offset,
+            |n: &i32| n % hidden_every == 0,
+            |limit, offset| {
+                reads += 1;
+                let page = (offset as i32..total)
+                    .take(limit as usize)
+                    .collect::<Vec<_>>();
+                async move { Ok(page) }
+            },
+        ))
+        .unwrap();
+        (page, reads)
+    }
+
+    #[test]
+    fn skips_hidden_records() {
+        let (page, _) = page_of(50, 3, 5, 0);
+        assert_eq!(page, [1, 2, 4, 5, 7]);
+        let (page, _) = page_of(50, 3, 5, 5);
+        assert_eq!(page, [8, 10, 11, 13, 14]);
+    }
+
+    #[test]
+    fn reads_past_a_page_of_hidden_records() {
+        let (page, reads) = page_of(250, 2, 60, 40);
+        assert_eq!(page.len(), 60);
+        assert_eq!(page.first(), Some(&81));
+        assert_eq!(page.last(), Some(&199));
+        assert_eq!(reads, 2);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_list() {
+        let (page, reads) = page_of(10, 2, 20, 3);
+        assert_eq!(page, [7, 9]);
+        assert_eq!(reads, 1);
+        let (page, _) = page_of(10, 2, 20, 30);
+        assert!(page.is_empty());
+    }
+}
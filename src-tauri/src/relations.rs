@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::client::Api;
+use crate::models::{ArchivedEntity, Brand, Category, Supplier};
+use crate::recycle_bin;
+
+/// The brand, categories and suppliers a product is listed under.
+#[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ProductRelations {
+    #[serde(default)]
+    pub brand_id: Option<i32>,
+    #[serde(default)]
+    pub category_ids: Vec<i32>,
+    #[serde(default)]
+    pub supplier_ids: Vec<i32>,
+}
+
+/// Every brand, category and supplier that isn't in the recycle bin. Links between products
+/// and the rest are kept on the brands, categories and suppliers, so one read of each covers
+/// any number of products.
+pub struct Relations {
+    brands: Vec<Brand>,
+    categories: Vec<Category>,
+    suppliers: Vec<Supplier>,
+}
+
+fn lists(products: &[Option<i32>], product_id: i32) -> bool {
+    products.contains(&Some(product_id))
+}
+
+/// A brand, category or supplier before and after a product is listed under it or taken off.
+enum Relink {
+    Brand(Brand, Brand),
+    Category(Category, Category),
+    Supplier(Supplier, Supplier),
+}
+
+/// Adds a product to a list or takes it off. Returns whether the list changed.
+fn relist(products: &mut Vec<Option<i32>>, product_id: i32, listed: bool) -> bool {
+    if listed == lists(products, product_id) {
+        return false;
+    }
+    if listed {
+        products.push(Some(product_id));
+    } else {
+        products.retain(|id| *id != Some(product_id));
+    }
+    true
+}
+
+fn replace<T>(records: &mut [T], after: T, id: impl Fn(&T) -> i32) {
+    if let Some(record) = records.iter_mut().find(|record| id(record) == id(&after)) {
+        *record = after;
+    }
+}
+
+impl Relink {
+    async fn save(&self, api: &Api, undo: bool) -> Result<(), Error> {
+        match self {
+            Relink::Brand(before, after) => {
+                let (from, to) = if undo {
+                    (after, before)
+                } else {
+                    (before, after)
+                };
+                api.update_brand(to).await?;
+                audit::updated(api, "brand", to.id, from, to).await
+            }
+            Relink::Category(before, after) => {
+                let (from, to) = if undo {
+                    (after, before)
+                } else {
+                    (before, after)
+                };
+                api.update_category(to).await?;
+                audit::updated(api, "category", to.id, from, to).await
+            }
+            Relink::Supplier(before, after) => {
+                let (from, to) = if undo {
+                    (after, before)
+                } else {
+                    (before, after)
+                };
+                api.update_supplier(to).await?;
+                audit::updated(api, "supplier", to.id, from, to).await
+            }
+        }
+    }
+}
+
+impl Relations {
+    pub async fn load(api: &Api) -> Result<Self, Error> {
+        let archived = recycle_bin::archived(api).await?;
+        Ok(Relations {
+            brands: api
+                .all_brands()
+                .await?
+                .into_iter()
+                .filter(|brand| !archived.contains(ArchivedEntity::Brand, brand.id))
+                .collect(),
+            categories: api
+                .all_categories()
+                .await?
+                .into_iter()
+                .filter(|category| !archived.contains(ArchivedEntity::Category, category.id))
+                .collect(),
+            suppliers: api
+                .all_suppliers()
+                .await?
+                .into_iter()
+                .filter(|supplier| !archived.contains(ArchivedEntity::Supplier, supplier.id))
+                .collect(),
+        })
+    }
+
+    pub fn of(&self, product_id: i32) -> ProductRelations {
+        ProductRelations {
+            brand_id: self
+                .brands
+                .iter()
+                .find(|brand| lists(&brand.products, product_id))
+                .map(|brand| brand.id),
+            category_ids: self
+                .categories
+                .iter()
+                .filter(|category| lists(&category.products, product_id))
+                .map(|category| category.id)
+                .collect(),
+            supplier_ids: self
+                .suppliers
+                .iter()
+                .filter(|supplier| lists(&supplier.products, product_id))
+                .map(|supplier| supplier.id)
+                .collect(),
+        }
+    }
+
+    /// Checks that everything a product is to be listed under exists.
+    pub fn check(&self, relations: &ProductRelations) -> Result<(), Error> {
+        if let Some(id) = relations.brand_id {
+            if !self.brands.iter().any(|brand| brand.id == id) {
+                return Err(anyhow!("Brand #{} doesn't exist", id));
+            }
+        }
+        for id in &relations.category_ids {
+            if !self.categories.iter().any(|category| category.id == *id) {
+                return Err(anyhow!("Category #{} doesn't exist", id));
+            }
+        }
+        for id in &relations.supplier_ids {
+            if !self.suppliers.iter().any(|supplier| supplier.id == *id) {
+                return Err(anyhow!("Supplier #{} doesn't exist", id));
+            }
+        }
+        Ok(())
+    }
+
+    fn relinks(&self, product_id: i32, relations: &ProductRelations) -> Vec<Relink> {
+        let mut relinks = Vec::new();
+        for brand in &self.brands {
+            let mut after = brand.clone();
+            if relist(
+                &mut after.products,
+                product_id,
+                relations.brand_id == Some(brand.id),
+            ) {
+                relinks.push(Relink::Brand(brand.clone(), after));
+            }
+        }
+        for category in &self.categories {
+            let mut after = category.clone();
+            if relist(
+                &mut after.products,
+                product_id,
+                relations.category_ids.contains(&category.id),
+            ) {
+                relinks.push(Relink::Category(category.clone(), after));
+            }
+        }
+        for supplier in &self.suppliers {
+            let mut after = supplier.clone();
+            if relist(
+                &mut after.products,
+                product_id,
+                relations.supplier_ids.contains(&supplier.id),
+            ) {
+                relinks.push(Relink::Supplier(supplier.clone(), after));
+            }
+        }
+        relinks
+    }
+
+    /// Lists a product under exactly the given brand, categories and suppliers. The backend has
+    /// no transactions, so if one save fails the ones before it are put back. On success the
+    /// lists here are brought up to date, so the same `Relations` can save the next product.
+    pub async fn save(
+        &mut self,
+        api: &Api,
+        product_id: i32,
+        relations: &ProductRelations,
+    ) -> Result<(), Error> {
+        self.check(relations)?;
+        let relinks = self.relinks(product_id, relations);
+        for (done, relink) in relinks.iter().enumerate() {
+            if let Err(err) = relink.save(api, false).await {
+                for relink in relinks[..done].iter().rev() {
+                    relink.save(api, true).await?;
+                }
+                return Err(err);
+            }
+        }
+        for relink in relinks {
+            match relink {
+                Relink::Brand(_, after) => replace(&mut self.brands, after, |brand| brand.id),
+                Relink::Category(_, after) => {
+                    replace(&mut self.categories, after, |category| category.id)
+                }
+                Relink::Supplier(_, after) => {
+                    replace(&mut self.suppliers, after, |supplier| supplier.id)
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::client::Api;
+use crate::models::{AdjustmentReason, ReturnLine, ReturnStatus, SerialStatus, VendorReturn};
+use crate::quantity::Quantity;
+use crate::serials::{check_serials, unstock_serials};
+use crate::stock::post_movement;
+use crate::{audit, err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppReturnLine {
+    product_id: i32,
+    received_order_id: Option<i32>,
+    amount: Quantity,
+    cost_price: String,
+    #[serde(default)]
+    serials: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppVendorReturn {
+    id: i32,
+    supplier_id: i32,
+    location_id: Option<i32>,
+    rma_number: String,
+    status: ReturnStatus,
+    lines: Vec<AppReturnLine>,
+    credit_expected: String,
+    credit_received: String,
+    note: String,
+    created: String,
+    shipped: String,
+    credited: String,
+}
+
+fn format_time(time: Option<NaiveDateTime>) -> String {
+    time.map(|time| time.format("%m/%d/%Y %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+impl AppReturnLine {
+    fn from_line(line: ReturnLine) -> Self {
+        AppReturnLine {
+            product_id: line.product_id,
+            received_order_id: line.received_order_id,
+            amount: line.amount,
+            cost_price: line.cost_per_unit.to_string(),
+            serials: line.serials,
+        }
+    }
+    fn to_line(&self) -> Result<ReturnLine, Error> {
+        Ok(ReturnLine {
+            product_id: self.product_id,
+            received_order_id: self.received_order_id,
+            amount: self.amount.clone(),
+            cost_per_unit: BigDecimal::from_str(&self.cost_price)?,
+            serials: self.serials.clone(),
+        })
+    }
+}
+
+impl AppVendorReturn {
+    fn from_return(vendor_return: VendorReturn) -> Self {
+        AppVendorReturn {
+            id: vendor_return.id,
+            supplier_id: vendor_return.supplier_id,
+            location_id: vendor_return.location_id,
+            rma_number: vendor_return.rma_number,
+            status: vendor_return.status,
+            lines: vendor_return
+                .lines
+                .into_iter()
+                .map(AppReturnLine::from_line)
+                .collect(),
+            credit_expected: vendor_return.credit_expected.to_string(),
+            credit_received: vendor_return
+                .credit_received
+                .map(|credit| credit.to_string())
+                .unwrap_or_default(),
+            note: vendor_return.note,
+            created: format_time(Some(vendor_return.created)),
+            shipped: format_time(vendor_return.shipped),
+            credited: format_time(vendor_return.credited),
+        }
+    }
+}
+
+async fn return_with_status(
+    api: &Api,
+    id: i32,
+    status: ReturnStatus,
+) -> Result<VendorReturn, Error> {
+    let vendor_return = api.get_vendor_return(id).await?;
+    if vendor_return.status != status {
+        return Err(anyhow!(
+            "Return is {:?}, expected {:?}",
+            vendor_return.status,
+            status
+        ));
+    }
+    Ok(vendor_return)
+}
+
+/// What the supplier charges for a product, or the product's own cost if the supplier's
+/// catalog doesn't list it.
+async fn supplier_cost(api: &Api, supplier_id: i32, product_id: i32) -> Result<BigDecimal, Error> {
+    let item = api
+        .get_product_supplier_items(product_id)
+        .await?
+        .into_iter()
+        .find(|item| item.supplier_id == supplier_id);
+    match item {
+        Some(item) => Ok(item.cost_per_unit),
+        None => Ok(api.get_product(product_id).await?.cost_price_per_unit),
+    }
+}
+
+fn credit_for(lines: &[ReturnLine]) -> BigDecimal {
+    lines
+        .iter()
+        .map(|line| line.amount.decimal() * &line.cost_per_unit)
+        .fold(BigDecimal::zero(), |total, credit| total + credit)
+        .round(2)
+}
+
+/// How much of a received order's damaged goods is already on a return.
+fn already_returned(returns: &[VendorReturn], received_order_id: i32) -> Quantity {
+    returns
+        .iter()
+        .flat_map(|vendor_return| &vendor_return.lines)
+        .filter(|line| line.received_order_id == Some(received_order_id))
+        .map(|line| &line.amount)
+        .sum()
+}
+
+async fn create(api: &Api, mut vendor_return: VendorReturn) -> Result<AppVendorReturn, Error> {
+    vendor_return.credit_expected = credit_for(&vendor_return.lines);
+    vendor_return.id = api.new_vendor_return(&vendor_return).await?;
+    audit::created(api, "vendor_return", vendor_return.id, &vendor_return).await?;
+    Ok(AppVendorReturn::from_return(vendor_return))
+}
+
+fn empty_return(supplier_id: i32, location_id: Option<i32>) -> VendorReturn {
+    VendorReturn {
+        id: 0,
+        supplier_id,
+        location_id,
+        rma_number: String::new(),
+        status: ReturnStatus::Requested,
+        lines: Vec::new(),
+        credit_expected: BigDecimal::zero(),
+        credit_received: None,
+        note: String::new(),
+        created: Utc::now().naive_utc(),
+        shipped: None,
+        credited: None,
+    }
+}
+
+/// Starts an empty return for goods that didn't come from a particular order.
+#[tauri::command]
+pub async fn new_vendor_return(
+    state: tauri::State<'_, AppState>,
+    supplier_id: i32,
+    location_id: Option<i32>,
+) -> Result<AppVendorReturn, String> {
+    let api = state.0.lock().await;
+    create(&api, empty_return(supplier_id, location_id))
+        .await
+        .map_err(err_to_string)
+}
+
+/// Starts a return for the damaged goods of received orders from one supplier. Goods that
+/// are already on another return are left out.
+#[tauri::command]
+pub async fn return_damaged(
+    state: tauri::State<'_, AppState>,
+    received_order_ids: Vec<i32>,
+    location_id: Option<i32>,
+) -> Result<AppVendorReturn, String> {
+    let api = state.0.lock().await;
+    let returns = api.all_vendor_returns().await.map_err(err_to_string)?;
+    let mut vendor_return: Option<VendorReturn> = None;
+    for id in received_order_ids {
+        let order = api.get_received_order(id).await.map_err(err_to_string)?;
+        let supplier_id = order
+            .supplier_id
+            .ok_or_else(|| format!("Received order #{} doesn't have a supplier", id))?;
+        let draft = vendor_return.get_or_insert_with(|| empty_return(supplier_id, location_id));
+        if draft.supplier_id != supplier_id {
+            return Err(String::from(
+                "Orders on a return have to be from one supplier",
+            ));
+        }
+        let amount = &order.damaged - &already_returned(&returns, id);
+        if !amount.is_positive() {
+            continue;
+        }
+        draft.lines.push(ReturnLine {
+            product_id: order.product_id,
+            received_order_id: Some(id),
+            amount,
+            cost_per_unit: supplier_cost(&api, supplier_id, order.product_id)
+                .await
+                .map_err(err_to_string)?,
+            serials: Vec::new(),
+        });
+    }
+    match vendor_return {
+        Some(vendor_return) if !vendor_return.lines.is_empty() => {
+            create(&api, vendor_return).await.map_err(err_to_string)
+        }
+        _ => Err(String::from("No damaged goods left to return")),
+    }
+}
+
+#[tauri::command]
+pub async fn get_vendor_returns(
+    state: tauri::State<'_, AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AppVendorReturn>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .get_vendor_returns(limit, offset)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(AppVendorReturn::from_return)
+        .collect())
+}
+
+/// Saves the RMA number, lines, expected credit and note of a return that hasn't shipped.
+/// A blank expected credit is worked out from the lines.
+#[tauri::command]
+pub async fn save_vendor_return(
+    state: tauri::State<'_, AppState>,
+    vendor_return: AppVendorReturn,
+) -> Result<AppVendorReturn, String> {
+    let lines = vendor_return
+        .lines
+        .iter()
+        .map(AppReturnLine::to_line)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(err_to_string)?;
+    if lines.iter().any(|line| !line.amount.is_positive()) {
+        return Err(String::from("Returned amounts have to be positive"));
+    }
+    let api = state.0.lock().await;
+    let mut stored = return_with_status(&api, vendor_return.id, ReturnStatus::Requested)
+        .await
+        .map_err(err_to_string)?;
+    let before = stored.clone();
+    stored.credit_expected = match vendor_return.credit_expected.trim() {
+        "" => credit_for(&lines),
+        credit => BigDecimal::from_str(credit)
+            .context("Credit is not a number")
+            .map_err(err_to_string)?,
+    };
+    stored.location_id = vendor_return.location_id;
+    stored.rma_number = vendor_return.rma_number.trim().to_string();
+    stored.lines = lines;
+    stored.note = vendor_return.note;
+    api.update_vendor_return(&stored)
+        .await
+        .map_err(err_to_string)?;
+    audit::updated(&api, "vendor_return", stored.id, &before, &stored)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppVendorReturn::from_return(stored))
+}
+
+#[tauri::command]
+pub async fn remove_vendor_return(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let vendor_return = return_with_status(&api, id, ReturnStatus::Requested)
+        .await
+        .map_err(err_to_string)?;
+    api.remove_vendor_return(id).await.map_err(err_to_string)?;
+    audit::deleted(&api, "vendor_return", id, &vendor_return)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+/// Takes the returned goods out of stock. Serialized products need the serial number of
+/// every item going back.
+#[tauri::command]
+pub async fn ship_vendor_return(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<AppVendorReturn, String> {
+    let api = state.0.lock().await;
+    let mut vendor_return = return_with_status(&api, id, ReturnStatus::Requested)
+        .await
+        .map_err(err_to_string)?;
+    if vendor_return.lines.is_empty() {
+        return Err(String::from("Return has nothing to ship"));
+    }
+    for line in &vendor_return.lines {
+        let product = api
+            .get_product(line.product_id)
+            .await
+            .map_err(err_to_string)?;
+        if product.track_serials {
+            check_serials(&line.serials, &line.amount).map_err(err_to_string)?;
+        } else if !line.serials.is_empty() {
+            return Err(format!("{} doesn't track serial numbers", product.name));
+        }
+    }
+    let note = match vendor_return.rma_number.as_str() {
+        "" => format!("Return to vendor #{}", vendor_return.id),
+        rma_number => format!(
+            "Return to vendor #{} (RMA {})",
+            vendor_return.id, rma_number
+        ),
+    };
+    for line in &vendor_return.lines {
+        if !line.serials.is_empty() {
+            unstock_serials(
+                &api,
+                line.product_id,
+                &line.serials,
+                SerialStatus::ReturnedToVendor,
+                &note,
+            )
+            .await
+            .map_err(err_to_string)?;
+        }
+        post_movement(
+            &api,
+            line.product_id,
+            vendor_return.location_id,
+            -&line.amount,
+            AdjustmentReason::ReturnedToVendor,
+            &note,
+        )
+        .await
+        .map_err(err_to_string)?;
+    }
+    vendor_return.status = ReturnStatus::Shipped;
+    vendor_return.shipped = Some(Utc::now().naive_utc());
+    api.update_vendor_return(&vendor_return)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppVendorReturn::from_return(vendor_return))
+}
+
+/// Records the credit the supplier gave for a shipped return, which may differ from what
+/// was expected.
+#[tauri::command]
+pub async fn credit_vendor_return(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+    credit_received: String,
+) -> Result<AppVendorReturn, String> {
+    let credit = BigDecimal::from_str(credit_received.trim())
+        .context("Credit is not a number")
+        .map_err(err_to_string)?;
+    if credit < BigDecimal::zero() {
+        return Err(String::from("Credit can't be negative"));
+    }
+    let api = state.0.lock().await;
+    let mut vendor_return = return_with_status(&api, id, ReturnStatus::Shipped)
+        .await
+        .map_err(err_to_string)?;
+    vendor_return.status = ReturnStatus::Credited;
+    vendor_return.credit_received = Some(credit);
+    vendor_return.credited = Some(Utc::now().naive_utc());
+    api.update_vendor_return(&vendor_return)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppVendorReturn::from_return(vendor_return))
+}
@@ -0,0 +1,336 @@
+use anyhow::{anyhow, Context, Error};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::client::Api;
+use crate::models::{AdjustmentReason, Product, Sale, SerialStatus, Unit};
+use crate::quantity::Quantity;
+use crate::serials::{check_serials, in_stock_serials, unstock_serials};
+use crate::stock::post_movement;
+use crate::units::to_base;
+use crate::{err_to_string, AppState};
+
+/// A sale line as entered. The quantity is in `unit`, the product's sale unit if none is
+/// given, and a blank price means the product's current price.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppSale {
+    id: i32,
+    product_id: i32,
+    location_id: Option<i32>,
+    quantity: Quantity,
+    #[serde(default)]
+    unit: Option<Unit>,
+    #[serde(default)]
+    price_per_unit: String,
+    #[serde(default)]
+    total_price: String,
+    #[serde(default)]
+    sold: String,
+    #[serde(default)]
+    user_name: String,
+    #[serde(default)]
+    serials: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UsageDay {
+    date: String,
+    quantity: Quantity,
+    revenue: String,
+}
+
+/// How much of a product sold per day over a period. Days without sales are left out of
+/// `days` but count towards the daily average.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UsageHistory {
+    product_id: i32,
+    days: Vec<UsageDay>,
+    total_quantity: Quantity,
+    total_revenue: String,
+    average_daily_quantity: Quantity,
+}
+
+impl AppSale {
+    /// A line in the product's sale unit, the way a till reports it.
+    pub fn new(
+        product_id: i32,
+        location_id: Option<i32>,
+        quantity: Quantity,
+        price_per_unit: String,
+        sold: NaiveDateTime,
+    ) -> Self {
+        AppSale {
+            id: 0,
+            product_id,
+            location_id,
+            quantity,
+            unit: None,
+            price_per_unit,
+            total_price: String::new(),
+            sold: sold.format("%m/%d/%Y %H:%M").to_string(),
+            user_name: String::new(),
+            serials: Vec::new(),
+        }
+    }
+
+    fn from_sale(sale: Sale, unit: Unit) -> Self {
+        AppSale {
+            id: sale.id,
+            product_id: sale.product_id,
+            location_id: sale.location_id,
+            quantity: sale.quantity,
+            unit: Some(unit),
+            price_per_unit: String::new(),
+            total_price: sale.total_price.to_string(),
+            sold: sale.sold.format("%m/%d/%Y %H:%M").to_string(),
+            user_name: sale.user_name,
+            serials: sale.serials,
+        }
+    }
+
+    /// Turns an entered line into a sale of the product in its base unit.
+    pub fn to_sale(&self, api: &Api, product: &Product) -> Result<Sale, Error> {
+        if !self.quantity.is_positive() {
+            return Err(anyhow!("Sold quantities have to be positive"));
+        }
+        if !product.variant_attributes.is_empty() {
+            return Err(anyhow!("Sell one of the variants of {}", product.name));
+        }
+        let unit = self.unit.unwrap_or(product.sale_unit);
+        let quantity = to_base(product, &self.quantity, unit)?;
+        let total_price = match self.price_per_unit.trim() {
+            "" => quantity.decimal() * current_price(product),
+            price => {
+                self.quantity.decimal()
+                    * BigDecimal::from_str(price).context("Price is not a number")?
+            }
+        }
+        .round(2);
+        let sold = match self.sold.trim() {
+            "" => Utc::now().naive_utc(),
+            sold => NaiveDateTime::parse_from_str(sold, "%m/%d/%Y %H:%M")
+                .context("Can't convert string to datetime")?,
+        };
+        if product.track_serials {
+            check_serials(&self.serials, &quantity)?;
+        } else if !self.serials.is_empty() {
+            return Err(anyhow!("{} doesn't track serial numbers", product.name));
+        }
+        Ok(Sale {
+            id: 0,
+            product_id: product.id,
+            location_id: self.location_id,
+            quantity,
+            total_price,
+            sold,
+            user_name: api.user_name().to_string(),
+            serials: self.serials.clone(),
+        })
+    }
+}
+
+/// The price of a product right now, which is the sale price while a sale is on.
+fn current_price(product: &Product) -> &BigDecimal {
+    match (&product.sale_price, product.sale_end) {
+        (Some(sale_price), Some(sale_end)) if sale_end > Utc::now().naive_utc() => sale_price,
+        _ => &product.selling_price_per_unit,
+    }
+}
+
+/// Records a sale and takes it out of stock. Its serials are checked before anything is
+/// recorded.
+pub async fn post_sale(api: &Api, mut sale: Sale) -> Result<Sale, Error> {
+    in_stock_serials(api, sale.product_id, &sale.serials).await?;
+    sale.id = api.new_sale(&sale).await?;
+    let note = format!("Sale #{}", sale.id);
+    if !sale.serials.is_empty() {
+        unstock_serials(
+            api,
+            sale.product_id,
+            &sale.serials,
+            SerialStatus::Sold,
+            &note,
+        )
+        .await?;
+    }
+    post_movement(
+        api,
+        sale.product_id,
+        sale.location_id,
+        -&sale.quantity,
+        AdjustmentReason::Sale,
+        &note,
+    )
+    .await?;
+    Ok(sale)
+}
+
+#[tauri::command]
+pub async fn record_sale(
+    state: tauri::State<'_, AppState>,
+    sale: AppSale,
+) -> Result<AppSale, String> {
+    let api = state.0.lock().await;
+    let product = api
+        .get_product(sale.product_id)
+        .await
+        .map_err(err_to_string)?;
+    let sale = sale.to_sale(&api, &product).map_err(err_to_string)?;
+    let sale = post_sale(&api, sale).await.map_err(err_to_string)?;
+    Ok(AppSale::from_sale(sale, product.base_unit))
+}
+
+/// Records a batch of sales, such as a day's takings. Every line is checked before any of
+/// them is posted, including that its serials are in stock and not sold on another line, so
+/// a bad line doesn't leave half the batch recorded.
+#[tauri::command]
+pub async fn record_sales(
+    state: tauri::State<'_, AppState>,
+    sales: Vec<AppSale>,
+) -> Result<Vec<AppSale>, String> {
+    let api = state.0.lock().await;
+    let mut products: HashMap<i32, Product> = HashMap::new();
+    let mut serials = HashSet::new();
+    let mut checked = Vec::new();
+    for (line, sale) in sales.iter().enumerate() {
+        let product = match products.entry(sale.product_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                api.get_product(sale.product_id)
+                    .await
+                    .map_err(err_to_string)?,
+            ),
+        };
+        let sale = sale
+            .to_sale(&api, product)
+            .map_err(|err| format!("Line {}: {}", line + 1, err))?;
+        in_stock_serials(&api, sale.product_id, &sale.serials)
+            .await
+            .map_err(|err| format!("Line {}: {}", line + 1, err))?;
+        if let Some(serial) = sale
+            .serials
+            .iter()
+            .find(|serial| !serials.insert((sale.product_id, serial.trim().to_string())))
+        {
+            return Err(format!(
+                "Line {}: Serial number {} is sold on another line",
+                line + 1,
+                serial
+            ));
+        }
+        checked.push(sale);
+    }
+    let mut posted = Vec::new();
+    for sale in checked {
+        let unit = products[&sale.product_id].base_unit;
+        posted.push(AppSale::from_sale(
+            post_sale(&api, sale).await.map_err(err_to_string)?,
+            unit,
+        ));
+    }
+    Ok(posted)
+}
+
+#[tauri::command]
+pub async fn get_sales(
+    state: tauri::State<'_, AppState>,
+    limit: i64,
+    offset: i64,
+    product_id: Option<i32>,
+) -> Result<Vec<AppSale>, String> {
+    let api = state.0.lock().await;
+    let sales = match product_id {
+        Some(product_id) => {
+            let mut sales = api
+                .get_product_sales(product_id)
+                .await
+                .map_err(err_to_string)?;
+            sales.sort_by_key(|sale| std::cmp::Reverse(sale.sold));
+            sales
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect()
+        }
+        None => api.get_sales(limit, offset).await.map_err(err_to_string)?,
+    };
+    let mut units = HashMap::new();
+    let mut app_sales = Vec::new();
+    for sale in sales {
+        let unit = match units.entry(sale.product_id) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => *entry.insert(
+                api.get_product(sale.product_id)
+                    .await
+                    .map_err(err_to_string)?
+                    .base_unit,
+            ),
+        };
+        app_sales.push(AppSale::from_sale(sale, unit));
+    }
+    Ok(app_sales)
+}
+
+/// Daily sales of a product between two days, inclusive.
+#[tauri::command]
+pub async fn product_usage(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+    from: String,
+    to: String,
+) -> Result<UsageHistory, String> {
+    let from = NaiveDate::parse_from_str(&from, "%m/%d/%Y")
+        .context("Can't convert string to date")
+        .map_err(err_to_string)?;
+    let to = NaiveDate::parse_from_str(&to, "%m/%d/%Y")
+        .context("Can't convert string to date")
+        .map_err(err_to_string)?;
+    if to < from {
+        return Err(String::from("Period ends before it starts"));
+    }
+    let sales = state
+        .0
+        .lock()
+        .await
+        .get_product_sales(product_id)
+        .await
+        .map_err(err_to_string)?;
+    let mut days: BTreeMap<NaiveDate, (Quantity, BigDecimal)> = BTreeMap::new();
+    for sale in sales {
+        let date = sale.sold.date();
+        if date < from || date > to {
+            continue;
+        }
+        let (quantity, revenue) = days
+            .entry(date)
+            .or_insert_with(|| (Quantity::zero(), BigDecimal::zero()));
+        *quantity += sale.quantity;
+        *revenue += sale.total_price;
+    }
+    let total_quantity: Quantity = days.values().map(|(quantity, _)| quantity).sum();
+    let total_revenue = days
+        .values()
+        .fold(BigDecimal::zero(), |total, (_, revenue)| total + revenue);
+    let period_days = (to - from + Duration::days(1)).num_days();
+    Ok(UsageHistory {
+        product_id,
+        average_daily_quantity: Quantity::from(
+            total_quantity.decimal() / BigDecimal::from(period_days),
+        )
+        .round(3),
+        total_quantity,
+        total_revenue: total_revenue.to_string(),
+        days: days
+            .into_iter()
+            .map(|(date, (quantity, revenue))| UsageDay {
+                date: date.format("%m/%d/%Y").to_string(),
+                quantity,
+                revenue: revenue.to_string(),
+            })
+            .collect(),
+    })
+}
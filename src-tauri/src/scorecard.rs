@@ -0,0 +1,165 @@
+use anyhow::Context;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::models::{ArchivedEntity, ReceivedOrder};
+use crate::quantity::Quantity;
+use crate::recycle_bin;
+use crate::{err_to_string, AppState};
+
+/// How a supplier did on a set of orders. Rates are percentages and are left out when
+/// there's nothing to base them on.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Score {
+    orders: usize,
+    ordered: Quantity,
+    received: Quantity,
+    fill_rate: Option<String>,
+    damage_rate: Option<String>,
+    on_time_rate: Option<String>,
+    average_lead_time_days: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct MonthlyScore {
+    month: String,
+    #[serde(flatten)]
+    score: Score,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SupplierScore {
+    supplier_id: i32,
+    supplier_name: String,
+    #[serde(flatten)]
+    score: Score,
+    months: Vec<MonthlyScore>,
+}
+
+#[derive(Default)]
+struct Tally {
+    orders: usize,
+    ordered: Quantity,
+    received: Quantity,
+    damaged: Quantity,
+    with_expected: usize,
+    on_time: usize,
+    with_ordered: usize,
+    lead_time_days: i64,
+}
+
+/// A supplier's tally over the whole range and for each month of it, by year and month.
+type SupplierTally = (Tally, BTreeMap<(i32, u32), Tally>);
+
+fn percent(part: &BigDecimal, whole: &BigDecimal) -> Option<String> {
+    if whole.is_zero() {
+        return None;
+    }
+    Some((part * BigDecimal::from(100) / whole).round(1).to_string())
+}
+
+impl Tally {
+    fn add(&mut self, order: &ReceivedOrder, received: NaiveDate) {
+        self.orders += 1;
+        self.ordered += &order.gross_amount;
+        self.received += &order.actually_received;
+        self.damaged += &order.damaged;
+        if let Some(expected) = order.expected {
+            self.with_expected += 1;
+            if received <= expected {
+                self.on_time += 1;
+            }
+        }
+        if let Some(ordered) = order.ordered {
+            self.with_ordered += 1;
+            self.lead_time_days += (received - ordered).num_days();
+        }
+    }
+
+    fn score(&self) -> Score {
+        Score {
+            orders: self.orders,
+            ordered: self.ordered.clone(),
+            received: self.received.clone(),
+            fill_rate: percent(self.received.decimal(), self.ordered.decimal()),
+            damage_rate: percent(self.damaged.decimal(), self.received.decimal()),
+            on_time_rate: percent(
+                &BigDecimal::from(self.on_time as i64),
+                &BigDecimal::from(self.with_expected as i64),
+            ),
+            average_lead_time_days: match self.with_ordered {
+                0 => None,
+                count => Some(
+                    (BigDecimal::from(self.lead_time_days) / BigDecimal::from(count as i64))
+                        .round(1)
+                        .to_string(),
+                ),
+            },
+        }
+    }
+}
+
+/// Grades suppliers on the orders received from them between two days, inclusive. Orders
+/// that don't say which supplier they came from, and orders in the recycle bin, aren't
+/// counted.
+#[tauri::command]
+pub async fn supplier_scorecard(
+    state: tauri::State<'_, AppState>,
+    from: String,
+    to: String,
+    supplier_id: Option<i32>,
+) -> Result<Vec<SupplierScore>, String> {
+    let from = NaiveDate::parse_from_str(&from, "%m/%d/%Y")
+        .context("Can't convert string to date")
+        .map_err(err_to_string)?;
+    let to = NaiveDate::parse_from_str(&to, "%m/%d/%Y")
+        .context("Can't convert string to date")
+        .map_err(err_to_string)?;
+    let api = state.0.lock().await;
+    let archived = recycle_bin::archived(&api).await.map_err(err_to_string)?;
+    let mut tallies: BTreeMap<i32, SupplierTally> = BTreeMap::new();
+    for order in api.all_received_orders().await.map_err(err_to_string)? {
+        if archived.contains(ArchivedEntity::ReceivedOrder, order.id) {
+            continue;
+        }
+        let (order_supplier, received) = match (order.supplier_id, order.received) {
+            (Some(order_supplier), Some(received)) => (order_supplier, received.date()),
+            _ => continue,
+        };
+        if received < from
+            || received > to
+            || supplier_id.map_or(false, |supplier_id| supplier_id != order_supplier)
+        {
+            continue;
+        }
+        let (total, months) = tallies.entry(order_supplier).or_default();
+        total.add(&order, received);
+        months
+            .entry((received.year(), received.month()))
+            .or_default()
+            .add(&order, received);
+    }
+
+    let mut scores = Vec::new();
+    for (supplier_id, (total, months)) in tallies {
+        scores.push(SupplierScore {
+            supplier_id,
+            supplier_name: api
+                .get_supplier(supplier_id)
+                .await
+                .map_err(err_to_string)?
+                .name,
+            score: total.score(),
+            months: months
+                .iter()
+                .map(|((year, month), tally)| MonthlyScore {
+                    month: format!("{:02}/{}", month, year),
+                    score: tally.score(),
+                })
+                .collect(),
+        });
+    }
+    Ok(scores)
+}
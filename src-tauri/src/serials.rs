@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Error};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::client::Api;
+use crate::models::{Product, Serial, SerialEvent, SerialStatus};
+use crate::quantity::Quantity;
+use crate::{err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppSerialEvent {
+    status: SerialStatus,
+    date: String,
+    note: String,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SerialLifecycle {
+    serial: String,
+    product_id: i32,
+    product_name: String,
+    received_order_id: Option<i32>,
+    supplier_id: Option<i32>,
+    supplier_name: String,
+    received: String,
+    status: SerialStatus,
+    events: Vec<AppSerialEvent>,
+}
+
+/// Checks that a list of serial numbers covers exactly `quantity` items, one serial each.
+pub fn check_serials(serials: &[String], quantity: &Quantity) -> Result<(), Error> {
+    if !quantity.is_whole() {
+        return Err(anyhow!(
+            "Serialized products can only be handled in whole units"
+        ));
+    }
+    if Quantity::from(serials.len() as i32) != quantity.abs() {
+        return Err(anyhow!(
+            "Expected {} serial numbers but got {}",
+            quantity.abs(),
+            serials.len()
+        ));
+    }
+    if serials.iter().any(|serial| serial.trim().is_empty()) {
+        return Err(anyhow!("Serial numbers can't be blank"));
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = serials.iter().find(|serial| !seen.insert(serial.trim())) {
+        return Err(anyhow!("Serial number {} was entered twice", duplicate));
+    }
+    Ok(())
+}
+
+/// Stock of a serialized product is the serials it has in stock, so it can't be moved by an
+/// amount alone.
+pub fn check_unserialized(product: &Product) -> Result<(), Error> {
+    if product.track_serials {
+        return Err(anyhow!(
+            "Stock of {} has to be adjusted by serial number",
+            product.name
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that none of the given serials of a product is in stock already.
+pub async fn check_not_in_stock(
+    api: &Api,
+    product_id: i32,
+    serials: &[String],
+) -> Result<(), Error> {
+    if serials.is_empty() {
+        return Ok(());
+    }
+    let known = api.get_product_serials(product_id).await?;
+    for serial in serials {
+        let serial = serial.trim();
+        if known
+            .iter()
+            .any(|known| known.serial == serial && known.status == SerialStatus::InStock)
+        {
+            return Err(anyhow!("Serial number {} is already in stock", serial));
+        }
+    }
+    Ok(())
+}
+
+/// Puts serials into stock, either as new serials from a received order or by bringing
+/// back ones that had left stock.
+pub async fn stock_serials(
+    api: &Api,
+    product_id: i32,
+    serials: &[String],
+    received_order_id: Option<i32>,
+    supplier_id: Option<i32>,
+    date: NaiveDateTime,
+    note: &str,
+) -> Result<(), Error> {
+    let known = api.get_product_serials(product_id).await?;
+    for serial in serials {
+        let serial = serial.trim();
+        let event = SerialEvent {
+            status: SerialStatus::InStock,
+            date,
+            note: note.to_string(),
+        };
+        match known.iter().find(|known| known.serial == serial) {
+            Some(known) if known.status == SerialStatus::InStock => {
+                return Err(anyhow!("Serial number {} is already in stock", serial));
+            }
+            Some(known) => {
+                let mut known = known.clone();
+                known.status = SerialStatus::InStock;
+                known.events.push(event);
+                api.update_serial(&known).await?;
+            }
+            None => {
+                api.new_serial(&Serial {
+                    id: 0,
+                    product_id,
+                    serial: serial.to_string(),
+                    received_order_id,
+                    supplier_id,
+                    received: date,
+                    status: SerialStatus::InStock,
+                    events: vec![event],
+                })
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The serials of a product with the given numbers, all of which have to be in stock.
+pub async fn in_stock_serials(
+    api: &Api,
+    product_id: i32,
+    serials: &[String],
+) -> Result<Vec<Serial>, Error> {
+    if serials.is_empty() {
+        return Ok(Vec::new());
+    }
+    let known = api.get_product_serials(product_id).await?;
+    let mut found = Vec::new();
+    for serial in serials {
+        let serial = serial.trim();
+        match known
+            .iter()
+            .find(|known| known.serial == serial && known.status == SerialStatus::InStock)
+        {
+            Some(known) => found.push(known.clone()),
+            None => return Err(anyhow!("Serial number {} isn't in stock", serial)),
+        }
+    }
+    Ok(found)
+}
+
+/// Takes serials out of stock, giving each the new status.
+pub async fn unstock_serials(
+    api: &Api,
+    product_id: i32,
+    serials: &[String],
+    status: SerialStatus,
+    note: &str,
+) -> Result<(), Error> {
+    for mut serial in in_stock_serials(api, product_id, serials).await? {
+        serial.status = status;
+        serial.events.push(SerialEvent {
+            status,
+            date: Utc::now().naive_utc(),
+            note: note.to_string(),
+        });
+        api.update_serial(&serial).await?;
+    }
+    Ok(())
+}
+
+/// The serial numbers of a product that are currently in stock.
+#[tauri::command]
+pub async fn product_serials(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+) -> Result<Vec<String>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .get_product_serials(product_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .filter(|serial| serial.status == SerialStatus::InStock)
+        .map(|serial| serial.serial)
+        .collect())
+}
+
+/// Everything known about a serial number. Different products can share a serial number,
+/// so there may be more than one match.
+#[tauri::command]
+pub async fn serial_lookup(
+    state: tauri::State<'_, AppState>,
+    serial: &str,
+) -> Result<Vec<SerialLifecycle>, String> {
+    let api = state.0.lock().await;
+    let mut lifecycles = Vec::new();
+    for serial in api
+        .find_serials(serial.trim())
+        .await
+        .map_err(err_to_string)?
+    {
+        let product = api
+            .get_product(serial.product_id)
+            .await
+            .map_err(err_to_string)?;
+        let supplier_name = match serial.supplier_id {
+            Some(id) => api.get_supplier(id).await.map_err(err_to_string)?.name,
+            None => String::new(),
+        };
+        lifecycles.push(SerialLifecycle {
+            serial: serial.serial,
+            product_id: product.id,
+            product_name: product.name,
+            received_order_id: serial.received_order_id,
+            supplier_id: serial.supplier_id,
+            supplier_name,
+            received: serial.received.format("%m/%d/%Y").to_string(),
+            status: serial.status,
+            events: serial
+                .events
+                .into_iter()
+                .map(|event| AppSerialEvent {
+                    status: event.status,
+                    date: event.date.format("%m/%d/%Y %H:%M").to_string(),
+                    note: event.note,
+                })
+                .collect(),
+        });
+    }
+    Ok(lifecycles)
+}
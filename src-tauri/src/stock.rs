@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Error};
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::client::Api;
+use crate::lots;
+use crate::models::{AdjustmentReason, LocationStock, SerialStatus, StockMovement, Unit};
+use crate::quantity::Quantity;
+use crate::serials::{check_serials, stock_serials, unstock_serials};
+use crate::units::to_base;
+use crate::{err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppStockMovement {
+    id: i32,
+    product_id: i32,
+    location_id: Option<i32>,
+    delta: Quantity,
+    reason: AdjustmentReason,
+    user_name: String,
+    note: String,
+    created: String,
+}
+
+/// A stock adjustment as entered. The delta is in `unit`, the product's base unit if none is
+/// given, and serialized products list the serials that come or go.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppStockAdjustment {
+    product_id: i32,
+    delta: Quantity,
+    #[serde(default)]
+    location_id: Option<i32>,
+    reason: AdjustmentReason,
+    note: String,
+    #[serde(default)]
+    serials: Vec<String>,
+    #[serde(default)]
+    unit: Option<Unit>,
+}
+
+impl AppStockMovement {
+    fn from_movement(movement: StockMovement) -> Self {
+        AppStockMovement {
+            id: movement.id,
+            product_id: movement.product_id,
+            location_id: movement.location_id,
+            delta: movement.delta,
+            reason: movement.reason,
+            user_name: movement.user_name,
+            note: movement.note,
+            created: movement.created.format("%m/%d/%Y %H:%M").to_string(),
+        }
+    }
+}
+
+impl AdjustmentReason {
+    /// Whether a user may pick this reason for a manual adjustment. The others are only
+    /// written by the app itself.
+    fn is_manual(&self) -> bool {
+        matches!(
+            self,
+            AdjustmentReason::Shrinkage
+                | AdjustmentReason::Damage
+                | AdjustmentReason::Theft
+                | AdjustmentReason::FoundStock
+                | AdjustmentReason::InternalUse
+                | AdjustmentReason::Correction
+        )
+    }
+}
+
+pub fn stock_on_hand(ledger: &[StockMovement]) -> Quantity {
+    ledger.iter().map(|movement| &movement.delta).sum()
+}
+
+pub fn stock_on_hand_at(ledger: &[StockMovement], location_id: i32) -> Quantity {
+    ledger
+        .iter()
+        .filter(|movement| movement.location_id == Some(location_id))
+        .map(|movement| &movement.delta)
+        .sum()
+}
+
+/// Values a quantity at the given unit cost, rounded to whole cents.
+pub fn value_at_cost(quantity: &Quantity, cost_price_per_unit: &BigDecimal) -> BigDecimal {
+    (quantity.decimal() * cost_price_per_unit).round(2)
+}
+
+async fn record_movement(
+    api: &Api,
+    product_id: i32,
+    location_id: Option<i32>,
+    delta: Quantity,
+    reason: AdjustmentReason,
+    note: &str,
+) -> Result<StockMovement, Error> {
+    let mut movement = StockMovement {
+        id: 0,
+        product_id,
+        location_id,
+        delta,
+        reason,
+        user_name: api.user_name().to_string(),
+        note: note.to_string(),
+        created: Utc::now().naive_utc(),
+    };
+    movement.id = api.new_stock_movement(&movement).await?;
+    Ok(movement)
+}
+
+/// Writes a movement to the ledger and sets the product's amount, and the amount at the
+/// movement's location if it has one, to the new ledger totals.
+pub async fn post_movement(
+    api: &Api,
+    product_id: i32,
+    location_id: Option<i32>,
+    delta: Quantity,
+    reason: AdjustmentReason,
+    note: &str,
+) -> Result<StockMovement, Error> {
+    let mut product = api.get_product(product_id).await?;
+    if !product.variant_attributes.is_empty() {
+        return Err(anyhow!("Stock of {} is kept on its variants", product.name));
+    }
+    let mut ledger = api.get_stock_movements(product_id).await?;
+    if ledger.is_empty() && !product.amount.is_zero() {
+        // Products that had stock before the ledger existed start from their stored amount.
+        // It isn't at any location yet, so it shows up as unassigned stock.
+        ledger.push(
+            record_movement(
+                api,
+                product_id,
+                None,
+                product.amount.clone(),
+                AdjustmentReason::Opening,
+                "Opening balance",
+            )
+            .await?,
+        );
+    }
+    let movement = record_movement(api, product_id, location_id, delta, reason, note).await?;
+    // Transfers only move stock around, so the lots it came from stay the same.
+    if movement.delta.is_negative()
+        && !matches!(
+            reason,
+            AdjustmentReason::TransferOut | AdjustmentReason::TransferIn
+        )
+    {
+        lots::consume_fefo(api, product_id, &movement.delta.abs()).await?;
+    }
+    ledger.push(movement.clone());
+    product.amount = stock_on_hand(&ledger);
+    api.update_product(&product).await?;
+
+    if let Some(location_id) = location_id {
+        let mut stock = api
+            .get_product_location_stock(product_id)
+            .await?
+            .into_iter()
+            .find(|stock| stock.location_id == location_id)
+            .unwrap_or(LocationStock {
+                location_id,
+                product_id,
+                amount: Quantity::zero(),
+                buy_level: None,
+            });
+        stock.amount = stock_on_hand_at(&ledger, location_id);
+        api.update_location_stock(&stock).await?;
+    }
+    Ok(movement)
+}
+
+#[tauri::command]
+pub async fn adjust_stock(
+    state: tauri::State<'_, AppState>,
+    adjustment: AppStockAdjustment,
+) -> Result<AppStockMovement, String> {
+    let AppStockAdjustment {
+        product_id,
+        delta,
+        location_id,
+        reason,
+        note,
+        serials,
+        unit,
+    } = adjustment;
+    if !reason.is_manual() {
+        return Err(String::from("Can't adjust stock with this reason"));
+    }
+    if delta.is_zero() {
+        return Err(String::from("Adjustment can't be zero"));
+    }
+    let api = state.0.lock().await;
+    let product = api.get_product(product_id).await.map_err(err_to_string)?;
+    let delta =
+        to_base(&product, &delta, unit.unwrap_or(product.base_unit)).map_err(err_to_string)?;
+    if product.track_serials {
+        check_serials(&serials, &delta).map_err(err_to_string)?;
+        if delta.is_negative() {
+            unstock_serials(&api, product_id, &serials, SerialStatus::WrittenOff, &note)
+                .await
+                .map_err(err_to_string)?;
+        } else {
+            stock_serials(
+                &api,
+                product_id,
+                &serials,
+                None,
+                None,
+                Utc::now().naive_utc(),
+                &note,
+            )
+            .await
+            .map_err(err_to_string)?;
+        }
+    }
+    Ok(AppStockMovement::from_movement(
+        post_movement(&api, product_id, location_id, delta, reason, &note)
+            .await
+            .map_err(err_to_string)?,
+    ))
+}
+
+#[tauri::command]
+pub async fn stock_ledger(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+) -> Result<Vec<AppStockMovement>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .get_stock_movements(product_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(AppStockMovement::from_movement)
+        .collect())
+}
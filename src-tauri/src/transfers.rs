@@ -0,0 +1,317 @@
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::client::Api;
+use crate::models::{AdjustmentReason, Transfer, TransferLine, TransferStatus};
+use crate::quantity::Quantity;
+use crate::serials::check_unserialized;
+use crate::stock::post_movement;
+use crate::{audit, err_to_string, AppState};
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppTransfer {
+    id: i32,
+    from_location_id: i32,
+    to_location_id: i32,
+    status: TransferStatus,
+    lines: Vec<TransferLine>,
+    created: String,
+    shipped: String,
+    received: String,
+}
+
+fn format_time(time: Option<NaiveDateTime>) -> String {
+    time.map(|time| time.format("%m/%d/%Y %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+impl AppTransfer {
+    fn from_transfer(transfer: Transfer) -> Self {
+        AppTransfer {
+            id: transfer.id,
+            from_location_id: transfer.from_location_id,
+            to_location_id: transfer.to_location_id,
+            status: transfer.status,
+            lines: transfer.lines,
+            created: format_time(Some(transfer.created)),
+            shipped: format_time(transfer.shipped),
+            received: format_time(transfer.received),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct InTransitLine {
+    transfer_id: i32,
+    from_location_id: i32,
+    to_location_id: i32,
+    product_id: i32,
+    amount: Quantity,
+    shipped: String,
+}
+
+/// Whether the goods of a transfer were on the road at the given time.
+fn in_transit_at(transfer: &Transfer, at: NaiveDateTime) -> bool {
+    match transfer.shipped {
+        Some(shipped) if shipped <= at => transfer.received.map_or(true, |received| received > at),
+        _ => false,
+    }
+}
+
+async fn transfer_with_status(
+    api: &Api,
+    id: i32,
+    status: TransferStatus,
+) -> Result<Transfer, Error> {
+    let transfer = api.get_transfer(id).await?;
+    if transfer.status != status {
+        return Err(anyhow!(
+            "Transfer is {:?}, expected {:?}",
+            transfer.status,
+            status
+        ));
+    }
+    Ok(transfer)
+}
+
+/// Serialized products can't be transferred, since their stock only moves by serial number.
+async fn check_lines(api: &Api, lines: &[TransferLine]) -> Result<(), Error> {
+    for line in lines {
+        check_unserialized(&api.get_product(line.product_id).await?)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn new_transfer(
+    state: tauri::State<'_, AppState>,
+    from_location_id: i32,
+    to_location_id: i32,
+) -> Result<AppTransfer, String> {
+    if from_location_id == to_location_id {
+        return Err(String::from("Can't transfer to the same location"));
+    }
+    let mut transfer = Transfer {
+        id: 0,
+        from_location_id,
+        to_location_id,
+        status: TransferStatus::Draft,
+        lines: Vec::new(),
+        created: Utc::now().naive_utc(),
+        shipped: None,
+        received: None,
+    };
+    let api = state.0.lock().await;
+    transfer.id = api.new_transfer(&transfer).await.map_err(err_to_string)?;
+    audit::created(&api, "transfer", transfer.id, &transfer)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppTransfer::from_transfer(transfer))
+}
+
+#[tauri::command]
+pub async fn get_transfers(
+    state: tauri::State<'_, AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AppTransfer>, String> {
+    Ok(state
+        .0
+        .lock()
+        .await
+        .get_transfers(limit, offset)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(AppTransfer::from_transfer)
+        .collect())
+}
+
+/// Saves the locations and lines of a draft transfer.
+#[tauri::command]
+pub async fn save_transfer(
+    state: tauri::State<'_, AppState>,
+    transfer: AppTransfer,
+) -> Result<(), String> {
+    if transfer.from_location_id == transfer.to_location_id {
+        return Err(String::from("Can't transfer to the same location"));
+    }
+    if transfer.lines.iter().any(|line| !line.amount.is_positive()) {
+        return Err(String::from("Transferred amounts have to be positive"));
+    }
+    let api = state.0.lock().await;
+    let mut stored = transfer_with_status(&api, transfer.id, TransferStatus::Draft)
+        .await
+        .map_err(err_to_string)?;
+    check_lines(&api, &transfer.lines)
+        .await
+        .map_err(err_to_string)?;
+    let before = stored.clone();
+    stored.from_location_id = transfer.from_location_id;
+    stored.to_location_id = transfer.to_location_id;
+    // Nothing of a draft has been posted yet.
+    stored.lines = transfer
+        .lines
+        .into_iter()
+        .map(|line| TransferLine {
+            shipped: false,
+            received: false,
+            ..line
+        })
+        .collect();
+    api.update_transfer(&stored).await.map_err(err_to_string)?;
+    audit::updated(&api, "transfer", stored.id, &before, &stored)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_transfer(state: tauri::State<'_, AppState>, id: i32) -> Result<(), String> {
+    let api = state.0.lock().await;
+    let transfer = transfer_with_status(&api, id, TransferStatus::Draft)
+        .await
+        .map_err(err_to_string)?;
+    api.remove_transfer(id).await.map_err(err_to_string)?;
+    audit::deleted(&api, "transfer", id, &transfer)
+        .await
+        .map_err(err_to_string)?;
+    Ok(())
+}
+
+/// Takes the goods out of the sending location. Until the transfer is received they are
+/// not part of any location's stock and show up in the in-transit report instead.
+#[tauri::command]
+pub async fn ship_transfer(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<AppTransfer, String> {
+    let api = state.0.lock().await;
+    let mut transfer = transfer_with_status(&api, id, TransferStatus::Draft)
+        .await
+        .map_err(err_to_string)?;
+    if transfer.lines.is_empty() {
+        return Err(String::from("Transfer has nothing to ship"));
+    }
+    check_lines(&api, &transfer.lines)
+        .await
+        .map_err(err_to_string)?;
+    for line in transfer.lines.iter().filter(|line| !line.shipped) {
+        let available = api
+            .get_product_location_stock(line.product_id)
+            .await
+            .map_err(err_to_string)?
+            .into_iter()
+            .find(|stock| stock.location_id == transfer.from_location_id)
+            .map_or_else(Quantity::zero, |stock| stock.amount);
+        if available < line.amount {
+            return Err(format!(
+                "Only {} of product #{} at the sending location",
+                available, line.product_id
+            ));
+        }
+    }
+    for index in 0..transfer.lines.len() {
+        let line = &transfer.lines[index];
+        if line.shipped {
+            continue;
+        }
+        post_movement(
+            &api,
+            line.product_id,
+            Some(transfer.from_location_id),
+            -&line.amount,
+            AdjustmentReason::TransferOut,
+            &format!("Transfer #{}", transfer.id),
+        )
+        .await
+        .map_err(err_to_string)?;
+        transfer.lines[index].shipped = true;
+        api.update_transfer(&transfer)
+            .await
+            .map_err(err_to_string)?;
+    }
+    transfer.status = TransferStatus::InTransit;
+    transfer.shipped = Some(Utc::now().naive_utc());
+    api.update_transfer(&transfer)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppTransfer::from_transfer(transfer))
+}
+
+#[tauri::command]
+pub async fn receive_transfer(
+    state: tauri::State<'_, AppState>,
+    id: i32,
+) -> Result<AppTransfer, String> {
+    let api = state.0.lock().await;
+    let mut transfer = transfer_with_status(&api, id, TransferStatus::InTransit)
+        .await
+        .map_err(err_to_string)?;
+    for index in 0..transfer.lines.len() {
+        let line = &transfer.lines[index];
+        if line.received {
+            continue;
+        }
+        post_movement(
+            &api,
+            line.product_id,
+            Some(transfer.to_location_id),
+            line.amount.clone(),
+            AdjustmentReason::TransferIn,
+            &format!("Transfer #{}", transfer.id),
+        )
+        .await
+        .map_err(err_to_string)?;
+        transfer.lines[index].received = true;
+        api.update_transfer(&transfer)
+            .await
+            .map_err(err_to_string)?;
+    }
+    transfer.status = TransferStatus::Received;
+    transfer.received = Some(Utc::now().naive_utc());
+    api.update_transfer(&transfer)
+        .await
+        .map_err(err_to_string)?;
+    Ok(AppTransfer::from_transfer(transfer))
+}
+
+/// Lists the goods that were in transit at the end of the given day, or right now if no
+/// day is given.
+#[tauri::command]
+pub async fn in_transit_report(
+    state: tauri::State<'_, AppState>,
+    date: Option<String>,
+) -> Result<Vec<InTransitLine>, String> {
+    let at = match date {
+        Some(date) => NaiveDate::parse_from_str(&date, "%m/%d/%Y")
+            .context("Can't convert string to date")
+            .map_err(err_to_string)?
+            .and_hms_opt(23, 59, 59)
+            .context("Can't convert date to datetime")
+            .map_err(err_to_string)?,
+        None => Utc::now().naive_utc(),
+    };
+    Ok(state
+        .0
+        .lock()
+        .await
+        .all_transfers()
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .filter(|transfer| in_transit_at(transfer, at))
+        .flat_map(|transfer| {
+            let shipped = format_time(transfer.shipped);
+            transfer.lines.into_iter().map(move |line| InTransitLine {
+                transfer_id: transfer.id,
+                from_location_id: transfer.from_location_id,
+                to_location_id: transfer.to_location_id,
+                product_id: line.product_id,
+                amount: line.amount,
+                shipped: shipped.clone(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Error};
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::models::{Product, Unit};
+use crate::quantity::Quantity;
+use crate::{err_to_string, AppState};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Dimension {
+    Count,
+    Mass,
+    Volume,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DisplayQuantity {
+    unit: Unit,
+    quantity: Quantity,
+    label: String,
+}
+
+impl Unit {
+    fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Each | Unit::Case => Dimension::Count,
+            Unit::Kilogram | Unit::Gram | Unit::Pound | Unit::Ounce => Dimension::Mass,
+            Unit::Litre | Unit::Millilitre => Dimension::Volume,
+        }
+    }
+
+    /// Size of the unit in each, kilograms or litres. Cases depend on the product.
+    fn factor(&self) -> BigDecimal {
+        let factor = match self {
+            Unit::Each | Unit::Case => "1",
+            Unit::Kilogram => "1",
+            Unit::Gram => "0.001",
+            Unit::Pound => "0.45359237",
+            Unit::Ounce => "0.028349523125",
+            Unit::Litre => "1",
+            Unit::Millilitre => "0.001",
+        };
+        BigDecimal::from_str(factor).unwrap()
+    }
+
+    /// How many decimal places a quantity in this unit may have.
+    pub fn precision(&self) -> i64 {
+        match self {
+            Unit::Each | Unit::Case | Unit::Gram | Unit::Millilitre => 0,
+            Unit::Ounce => 2,
+            Unit::Kilogram | Unit::Pound | Unit::Litre => 3,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Each => "ea",
+            Unit::Case => "cs",
+            Unit::Kilogram => "kg",
+            Unit::Gram => "g",
+            Unit::Pound => "lb",
+            Unit::Ounce => "oz",
+            Unit::Litre => "L",
+            Unit::Millilitre => "mL",
+        }
+    }
+}
+
+/// How many base units of a product make up one of the given unit. A case holds
+/// `case_size` base units.
+fn base_units_per(product: &Product, unit: Unit) -> Result<BigDecimal, Error> {
+    if unit == product.base_unit {
+        return Ok(BigDecimal::from(1));
+    }
+    if unit == Unit::Case {
+        return match product.case_size {
+            Some(case_size) if case_size > 0 => Ok(BigDecimal::from(case_size)),
+            _ => Err(anyhow!("{} has no case size", product.name)),
+        };
+    }
+    if unit.dimension() != product.base_unit.dimension() {
+        return Err(anyhow!(
+            "Can't measure {} in {}",
+            product.name,
+            unit.symbol()
+        ));
+    }
+    Ok(unit.factor() / product.base_unit.factor())
+}
+
+/// How many decimal places a quantity of a product in the given unit may have. Products
+/// weighed before units existed have each as their base unit, and their each is a weight,
+/// so it's left unlimited for them.
+fn precision(product: &Product, unit: Unit) -> Option<i64> {
+    if unit == Unit::Each && product.base_unit == Unit::Each && product.measure_by_weight {
+        return None;
+    }
+    Some(unit.precision())
+}
+
+/// Rejects quantities more precise than their unit allows, like half an each.
+pub fn check_precision(product: &Product, quantity: &Quantity, unit: Unit) -> Result<(), Error> {
+    match precision(product, unit) {
+        Some(precision) if quantity.round(precision) != *quantity => Err(anyhow!(
+            "Quantities in {} can have at most {} decimal places",
+            unit.symbol(),
+            precision
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Converts a quantity of a product entered in the given unit to its base unit, rounded to
+/// what the base unit allows. Imperial weights and volumes rarely convert exactly.
+pub fn to_base(product: &Product, quantity: &Quantity, unit: Unit) -> Result<Quantity, Error> {
+    check_precision(product, quantity, unit)?;
+    let factor = base_units_per(product, unit)?;
+    let converted = Quantity::from(quantity.decimal() * factor);
+    Ok(match precision(product, product.base_unit) {
+        Some(precision) => converted.round(precision),
+        None => converted,
+    })
+}
+
+/// Converts a base quantity of a product to the given unit for display.
+pub fn from_base(product: &Product, quantity: &Quantity, unit: Unit) -> Result<Quantity, Error> {
+    let factor = base_units_per(product, unit)?;
+    if factor.is_zero() {
+        return Err(anyhow!(
+            "Can't measure {} in {}",
+            product.name,
+            unit.symbol()
+        ));
+    }
+    Ok(Quantity::from(quantity.decimal() / factor).round(unit.precision().max(3)))
+}
+
+pub fn convert(
+    product: &Product,
+    quantity: &Quantity,
+    from: Unit,
+    to: Unit,
+) -> Result<Quantity, Error> {
+    from_base(product, &to_base(product, quantity, from)?, to)
+}
+
+/// Checks that the purchase and sale units of a product can be converted to its base unit.
+/// A case can't be the base unit, since cases are counted in the each they hold.
+pub fn check_units(product: &Product) -> Result<(), Error> {
+    if product.base_unit == Unit::Case {
+        return Err(anyhow!(
+            "A case can't be the base unit, use each and set a case size"
+        ));
+    }
+    base_units_per(product, product.purchase_unit)?;
+    base_units_per(product, product.sale_unit)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn convert_quantity(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+    quantity: Quantity,
+    from: Unit,
+    to: Unit,
+) -> Result<Quantity, String> {
+    let product = state
+        .0
+        .lock()
+        .await
+        .get_product(product_id)
+        .await
+        .map_err(err_to_string)?;
+    convert(&product, &quantity, from, to).map_err(err_to_string)
+}
+
+/// A base quantity of a product in each of the units configured for it.
+#[tauri::command]
+pub async fn quantity_in_units(
+    state: tauri::State<'_, AppState>,
+    product_id: i32,
+    quantity: Quantity,
+) -> Result<Vec<DisplayQuantity>, String> {
+    let product = state
+        .0
+        .lock()
+        .await
+        .get_product(product_id)
+        .await
+        .map_err(err_to_string)?;
+    let mut units = vec![product.base_unit, product.purchase_unit, product.sale_unit];
+    if product.case_size.map_or(false, |case_size| case_size > 0) {
+        units.push(Unit::Case);
+    }
+    let mut quantities: Vec<DisplayQuantity> = Vec::new();
+    for unit in units {
+        if quantities.iter().any(|quantity| quantity.unit == unit) {
+            continue;
+        }
+        let converted = from_base(&product, &quantity, unit).map_err(err_to_string)?;
+        quantities.push(DisplayQuantity {
+            unit,
+            label: format!("{} {}", converted, unit.symbol()),
+            quantity: converted,
+        });
+    }
+    Ok(quantities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(s: &str) -> Quantity {
+        Quantity::from_str(s).unwrap()
+    }
+
+    fn weighed_in(base_unit: Unit) -> Product {
+        Product {
+            base_unit,
+            measure_by_weight: true,
+            ..Product::sample(1, "Flour")
+        }
+    }
+
+    #[test]
+    fn converts_to_base_rounded_to_its_precision() {
+        let flour = weighed_in(Unit::Kilogram);
+        assert_eq!(
+            to_base(&flour, &quantity("1"), Unit::Pound).unwrap(),
+            quantity("0.454")
+        );
+        assert_eq!(
+            to_base(&flour, &quantity("3.5"), Unit::Ounce).unwrap(),
+            quantity("0.099")
+        );
+        assert_eq!(
+            to_base(&flour, &quantity("250"), Unit::Gram).unwrap(),
+            quantity("0.25")
+        );
+    }
+
+    #[test]
+    fn rejects_quantities_finer_than_their_unit() {
+        let flour = weighed_in(Unit::Kilogram);
+        assert!(to_base(&flour, &quantity("0.5"), Unit::Gram).is_err());
+        assert!(to_base(&flour, &quantity("0.0005"), Unit::Kilogram).is_err());
+    }
+
+    #[test]
+    fn converts_cases_by_case_size() {
+        let soap = Product {
+            case_size: Some(12),
+            ..Product::sample(1, "Soap")
+        };
+        assert_eq!(
+            to_base(&soap, &quantity("2"), Unit::Case).unwrap(),
+            quantity("24")
+        );
+        assert_eq!(
+            from_base(&soap, &quantity("18"), Unit::Case).unwrap(),
+            quantity("1.5")
+        );
+        assert!(to_base(&Product::sample(2, "Rope"), &quantity("1"), Unit::Case).is_err());
+    }
+
+    #[test]
+    fn refuses_units_of_another_dimension() {
+        let flour = weighed_in(Unit::Kilogram);
+        assert!(to_base(&flour, &quantity("1"), Unit::Litre).is_err());
+        assert!(from_base(&flour, &quantity("1"), Unit::Millilitre).is_err());
+    }
+
+    #[test]
+    fn leaves_legacy_weighed_each_unlimited() {
+        let legacy = weighed_in(Unit::Each);
+        assert_eq!(
+            to_base(&legacy, &quantity("1.23456"), Unit::Each).unwrap(),
+            quantity("1.23456")
+        );
+        assert!(to_base(&Product::sample(2, "Soap"), &quantity("1.5"), Unit::Each).is_err());
+    }
+
+    #[test]
+    fn converts_from_base_for_display() {
+        let flour = weighed_in(Unit::Kilogram);
+        assert_eq!(
+            from_base(&flour, &quantity("1"), Unit::Pound).unwrap(),
+            quantity("2.205")
+        );
+        assert_eq!(
+            convert(&flour, &quantity("500"), Unit::Gram, Unit::Kilogram).unwrap(),
+            quantity("0.5")
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Error};
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::audit;
+use crate::client::Api;
+use crate::models::{Product, VariantOption};
+use crate::quantity::Quantity;
+use crate::stock::value_at_cost;
+use crate::{err_to_string, AppState};
+
+/// An attribute to build variants from, with every value it can take.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct VariantAttribute {
+    name: String,
+    values: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AppVariant {
+    product_id: i32,
+    name: String,
+    upc: String,
+    options: Vec<VariantOption>,
+    amount: Quantity,
+    buy_level: Option<Quantity>,
+    cost_price: String,
+    selling_price: String,
+    value_at_cost: String,
+}
+
+/// A parent product with the stock and value of all its variants added up.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct VariantRollup {
+    parent_id: i32,
+    name: String,
+    attributes: Vec<String>,
+    variants: Vec<AppVariant>,
+    amount: Quantity,
+    value_at_cost: String,
+}
+
+impl AppVariant {
+    fn from_product(product: Product) -> Self {
+        AppVariant {
+            product_id: product.id,
+            value_at_cost: value_at_cost(&product.amount, &product.cost_price_per_unit).to_string(),
+            name: product.name,
+            upc: product.upc,
+            options: product.variant_options,
+            amount: product.amount,
+            buy_level: product.buy_level,
+            cost_price: product.cost_price_per_unit.to_string(),
+            selling_price: product.selling_price_per_unit.to_string(),
+        }
+    }
+}
+
+fn check_attributes(attributes: &[VariantAttribute]) -> Result<(), Error> {
+    if attributes.is_empty() {
+        return Err(anyhow!("Variants need at least one attribute"));
+    }
+    let mut names = HashSet::new();
+    for attribute in attributes {
+        let name = attribute.name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Attribute names can't be blank"));
+        }
+        if !names.insert(name.to_lowercase()) {
+            return Err(anyhow!("Attribute {} was entered twice", name));
+        }
+        if attribute.values.is_empty() {
+            return Err(anyhow!("Attribute {} has no values", name));
+        }
+        let mut values = HashSet::new();
+        for value in &attribute.values {
+            if value.trim().is_empty() {
+                return Err(anyhow!("Values of {} can't be blank", name));
+            }
+            if !values.insert(value.trim().to_lowercase()) {
+                return Err(anyhow!("Value {} of {} was entered twice", value, name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every combination of one value per attribute, in the order the attributes were given.
+fn combinations(attributes: &[VariantAttribute]) -> Vec<Vec<VariantOption>> {
+    attributes
+        .iter()
+        .fold(vec![Vec::new()], |combinations, attribute| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    attribute.values.iter().map(move |value| {
+                        let mut combination: Vec<VariantOption> = combination.clone();
+                        combination.push(VariantOption {
+                            attribute: attribute.name.trim().to_string(),
+                            value: value.trim().to_string(),
+                        });
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+fn variant_name(parent: &Product, options: &[VariantOption]) -> String {
+    let values = options
+        .iter()
+        .map(|option| option.value.as_str())
+        .collect::<Vec<_>>();
+    format!("{} - {}", parent.name, values.join(" / "))
+}
+
+async fn rollup(api: &Api, parent: Product) -> Result<VariantRollup, Error> {
+    let variants = api.get_product_variants(parent.id).await?;
+    let amount = variants.iter().map(|variant| &variant.amount).sum();
+    let value = variants
+        .iter()
+        .map(|variant| value_at_cost(&variant.amount, &variant.cost_price_per_unit))
+        .fold(BigDecimal::zero(), |total, value| total + value);
+    Ok(VariantRollup {
+        parent_id: parent.id,
+        name: parent.name,
+        attributes: parent.variant_attributes,
+        variants: variants.into_iter().map(AppVariant::from_product).collect(),
+        amount,
+        value_at_cost: value.to_string(),
+    })
+}
+
+/// Creates a variant for every combination of the given attribute values that the parent
+/// doesn't have yet. Variants start out with the parent's prices, units, categories,
+/// suppliers and brand, and get their own UPC, stock and buy level.
+#[tauri::command]
+pub async fn create_variant_matrix(
+    state: tauri::State<'_, AppState>,
+    parent_id: i32,
+    attributes: Vec<VariantAttribute>,
+) -> Result<VariantRollup, String> {
+    check_attributes(&attributes).map_err(err_to_string)?;
+    let names = attributes
+        .iter()
+        .map(|attribute| attribute.name.trim().to_string())
+        .collect::<Vec<_>>();
+    let api = state.0.lock().await;
+    let mut parent = api.get_product(parent_id).await.map_err(err_to_string)?;
+    if parent.parent_id.is_some() {
+        return Err(String::from("Variants can't have variants of their own"));
+    }
+    if !parent.components.is_empty() {
+        return Err(String::from("Kits can't have variants"));
+    }
+    if parent.variant_attributes.is_empty() {
+        if !parent.amount.is_zero() {
+            return Err(format!(
+                "{} still has stock, adjust it to zero before adding variants",
+                parent.name
+            ));
+        }
+    } else if parent.variant_attributes != names {
+        return Err(format!(
+            "Variants of {} are by {}",
+            parent.name,
+            parent.variant_attributes.join(", ")
+        ));
+    }
+
+    let existing = api
+        .get_product_variants(parent_id)
+        .await
+        .map_err(err_to_string)?;
+    let categories = api
+        .get_product_categories(parent_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(|category| category.id)
+        .collect::<Vec<_>>();
+    let suppliers = api
+        .get_product_suppliers(parent_id)
+        .await
+        .map_err(err_to_string)?
+        .into_iter()
+        .map(|supplier| supplier.id)
+        .collect::<Vec<_>>();
+    let brand = api
+        .get_product_brand(parent_id)
+        .await
+        .map_err(err_to_string)?
+        .map(|brand| brand.id);
+    for options in combinations(&attributes) {
+        if existing
+            .iter()
+            .any(|variant| variant.variant_options == options)
+        {
+            continue;
+        }
+        let id = api
+            .new_product(
+                "",
+                &variant_name(&parent, &options),
+                &parent.description,
+                parent.measure_by_weight,
+                parent.cost_price_per_unit.clone(),
+                parent.selling_price_per_unit.clone(),
+                Quantity::zero(),
+                categories.clone(),
+                suppliers.clone(),
+                brand,
+            )
+            .await
+            .map_err(err_to_string)?;
+        let mut variant = api.get_product(id).await.map_err(err_to_string)?;
+        variant.case_size = parent.case_size;
+        variant.track_serials = parent.track_serials;
+        variant.base_unit = parent.base_unit;
+        variant.purchase_unit = parent.purchase_unit;
+        variant.sale_unit = parent.sale_unit;
+        variant.parent_id = Some(parent_id);
+        variant.variant_options = options;
+        api.update_product(&variant).await.map_err(err_to_string)?;
+        audit::created(&api, "product", id, &variant)
+            .await
+            .map_err(err_to_string)?;
+    }
+    let before = parent.clone();
+    parent.variant_attributes = names;
+    if parent != before {
+        api.update_product(&parent).await.map_err(err_to_string)?;
+        audit::updated(&api, "product", parent_id, &before, &parent)
+            .await
+            .map_err(err_to_string)?;
+    }
+    rollup(&api, parent).await.map_err(err_to_string)
+}
+
+#[tauri::command]
+pub async fn variant_rollup(
+    state: tauri::State<'_, AppState>,
+    parent_id: i32,
+) -> Result<VariantRollup, String> {
+    let api = state.0.lock().await;
+    let parent = api.get_product(parent_id).await.map_err(err_to_string)?;
+    if parent.variant_attributes.is_empty() {
+        return Err(format!("{} has no variants", parent.name));
+    }
+    rollup(&api, parent).await.map_err(err_to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(name: &str, values: &[&str]) -> VariantAttribute {
+        VariantAttribute {
+            name: name.to_string(),
+            values: values.iter().map(|value| value.to_string()).collect(),
+        }
+    }
+
+    fn labels(combinations: &[Vec<VariantOption>]) -> Vec<String> {
+        combinations
+            .iter()
+            .map(|options| {
+                options
+                    .iter()
+                    .map(|option| format!("{}={}", option.attribute, option.value))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn combines_every_value_of_every_attribute() {
+        let combinations = combinations(&[
+            attribute("Size", &["S", " M "]),
+            attribute(" Flavor", &["Mint", "Lemon", "Plain"]),
+        ]);
+        assert_eq!(
+            labels(&combinations),
+            [
+                "Size=S,Flavor=Mint",
+                "Size=S,Flavor=Lemon",
+                "Size=S,Flavor=Plain",
+                "Size=M,Flavor=Mint",
+                "Size=M,Flavor=Lemon",
+                "Size=M,Flavor=Plain",
+            ]
+        );
+    }
+
+    #[test]
+    fn an_attribute_without_values_leaves_no_combinations() {
+        assert!(combinations(&[attribute("Size", &["S"]), attribute("Flavor", &[])]).is_empty());
+        assert_eq!(combinations(&[]), [Vec::<VariantOption>::new()]);
+    }
+}